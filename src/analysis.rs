@@ -0,0 +1,343 @@
+//! Consistency checks over the latencies proven between many peers. A Proof
+//! of Latency only bounds the latency from above, so a peer lying about its
+//! location can make its latencies longer but not shorter. Such a peer shows
+//! up as edges that are longer than a detour through a third peer, i.e.
+//! violations of the triangle inequality.
+use crate::error::PoLError;
+use crate::latency::LatencyProof;
+use crate::store::StoredProof;
+use crate::vdf::group::VdfGroup;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// How much longer than a detour an edge may be, relative to the detour,
+/// before it violates the triangle inequality. The internet doesn't route
+/// along the shortest paths, so some violations happen between honest peers.
+pub const DEFAULT_TOLERANCE: f64 = 0.2;
+
+/// Score above which a peer is suspicious unless set otherwise
+pub const DEFAULT_SUSPICIOUS_SCORE: f64 = 0.25;
+
+/// An edge that is longer than the detour through another peer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Indices of the peers of the edge, the lower first
+    pub edge: (usize, usize),
+    pub via: usize,
+    pub direct: u64,
+    pub detour: u64,
+}
+
+/// How often an edge was the violating side of the triangles it is in
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeScore {
+    pub edge: (usize, usize),
+    pub latency: u64,
+    pub violations: usize,
+    pub triangles: usize,
+    /// The share of the triangles violated, from 0 to 1
+    pub score: f64,
+}
+
+/// How often the edges of a peer violate the triangle inequality
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerScore {
+    pub peer: Vec<u8>,
+    pub violations: usize,
+    pub triangles: usize,
+    /// The share of the triangles on the edges of the peer violated, from 0
+    /// to 1
+    pub score: f64,
+    pub suspicious: bool,
+}
+
+/// Result of TriangleCheck::check
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyReport {
+    /// Public keys of the peers, indexed by the edges and violations
+    pub peers: Vec<Vec<u8>>,
+    pub violations: Vec<Violation>,
+    /// Edges with violations, the highest score first
+    pub inconsistent_edges: Vec<EdgeScore>,
+    /// Every peer, the highest score first
+    pub scores: Vec<PeerScore>,
+}
+
+impl ConsistencyReport {
+    pub fn suspicious(&self) -> impl Iterator<Item = &PeerScore> {
+        self.scores.iter().filter(|score| score.suspicious)
+    }
+
+    pub fn score_of(&self, peer: &[u8]) -> Option<&PeerScore> {
+        self.scores.iter().find(|score| score.peer == peer)
+    }
+}
+
+/// Weighted graph of the peers, with the latency proven between two peers as
+/// the weight of their edge
+#[derive(Debug, Clone, Default)]
+pub struct LatencyGraph {
+    peers: Vec<Vec<u8>>,
+    indices: HashMap<Vec<u8>, usize>,
+    edges: HashMap<(usize, usize), u64>,
+}
+
+impl LatencyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Graph of proofs already verified by a ProofStore
+    pub fn from_stored(proofs: &[StoredProof]) -> Self {
+        let mut graph = Self::new();
+        for stored in proofs.iter() {
+            graph.add(
+                &stored.proof.prover_key,
+                &stored.proof.verifier_key,
+                stored.latency,
+            );
+        }
+        graph
+    }
+
+    /// Verifies the proof in group and adds its latency
+    pub fn add_proof<G: VdfGroup>(
+        &mut self,
+        group: &G,
+        proof: &LatencyProof,
+    ) -> Result<u64, PoLError> {
+        let latency = proof.verify(group)?;
+        self.add(&proof.prover_key, &proof.verifier_key, latency);
+        Ok(latency)
+    }
+
+    /// Adds a latency between two peers. Every latency is an upper bound, so
+    /// of several latencies between the same peers the lowest is kept.
+    pub fn add(&mut self, peer: &[u8], other: &[u8], latency: u64) {
+        let a = self.index(peer);
+        let b = self.index(other);
+        if a == b {
+            return;
+        }
+        let edge = self.edges.entry((a.min(b), a.max(b))).or_insert(latency);
+        *edge = (*edge).min(latency);
+    }
+
+    fn index(&mut self, peer: &[u8]) -> usize {
+        if let Some(index) = self.indices.get(peer) {
+            return *index;
+        }
+        self.peers.push(peer.to_vec());
+        self.indices.insert(peer.to_vec(), self.peers.len() - 1);
+        self.peers.len() - 1
+    }
+
+    pub fn peers(&self) -> &[Vec<u8>] {
+        &self.peers
+    }
+
+    /// Index of the peer in peers, if it has any latency
+    pub fn index_of(&self, peer: &[u8]) -> Option<usize> {
+        self.indices.get(peer).copied()
+    }
+
+    /// Latency between the peers at indices a and b
+    pub fn latency(&self, a: usize, b: usize) -> Option<u64> {
+        self.edges.get(&(a.min(b), a.max(b))).copied()
+    }
+
+    /// Every edge as the indices of its peers and its latency, in order
+    pub fn edges(&self) -> Vec<(usize, usize, u64)> {
+        let mut edges: Vec<(usize, usize, u64)> = self
+            .edges
+            .iter()
+            .map(|(&(a, b), &latency)| (a, b, latency))
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+}
+
+/// Options of the triangle inequality check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriangleCheck {
+    pub tolerance: f64,
+    pub suspicious_score: f64,
+}
+
+impl Default for TriangleCheck {
+    fn default() -> Self {
+        Self {
+            tolerance: DEFAULT_TOLERANCE,
+            suspicious_score: DEFAULT_SUSPICIOUS_SCORE,
+        }
+    }
+}
+
+impl TriangleCheck {
+    /// Checks every triangle of peers with all three latencies known
+    pub fn check(&self, graph: &LatencyGraph) -> ConsistencyReport {
+        let peers = graph.peers.len();
+
+        let mut violations = Vec::new();
+        let mut edge_scores = Vec::new();
+        let mut peer_violations = vec![0; peers];
+        let mut peer_triangles = vec![0; peers];
+        for (a, b, direct) in graph.edges() {
+            let mut edge_violations = 0;
+            let mut triangles = 0;
+            for via in (0..peers).filter(|via| *via != a && *via != b) {
+                let detour =
+                    match (graph.latency(a, via), graph.latency(via, b)) {
+                        (Some(first), Some(second)) => {
+                            first.saturating_add(second)
+                        }
+                        _ => continue,
+                    };
+                triangles += 1;
+                if direct as f64 > detour as f64 * (1.0 + self.tolerance) {
+                    edge_violations += 1;
+                    violations.push(Violation {
+                        edge: (a, b),
+                        via,
+                        direct,
+                        detour,
+                    });
+                }
+            }
+            for peer in [a, b].iter() {
+                peer_violations[*peer] += edge_violations;
+                peer_triangles[*peer] += triangles;
+            }
+            if edge_violations > 0 {
+                edge_scores.push(EdgeScore {
+                    edge: (a, b),
+                    latency: direct,
+                    violations: edge_violations,
+                    triangles,
+                    score: share(edge_violations, triangles),
+                });
+            }
+        }
+
+        let mut scores: Vec<PeerScore> = graph
+            .peers
+            .iter()
+            .enumerate()
+            .map(|(index, peer)| {
+                let score =
+                    share(peer_violations[index], peer_triangles[index]);
+                PeerScore {
+                    peer: peer.clone(),
+                    violations: peer_violations[index],
+                    triangles: peer_triangles[index],
+                    score,
+                    suspicious: score > self.suspicious_score,
+                }
+            })
+            .collect();
+        // Scores are shares, never NaN
+        edge_scores.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
+        });
+        scores.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
+        });
+
+        ConsistencyReport {
+            peers: graph.peers.clone(),
+            violations,
+            inconsistent_edges: edge_scores,
+            scores,
+        }
+    }
+}
+
+fn share(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(peer: u8) -> Vec<u8> {
+        vec![peer; 32]
+    }
+
+    /// Peers on a plane, latencies being ten times their distances
+    fn graph(liar_detour: Option<u64>) -> LatencyGraph {
+        let positions: [(f64, f64); 4] =
+            [(0.0, 0.0), (30.0, 0.0), (0.0, 40.0), (30.0, 40.0)];
+        let mut graph = LatencyGraph::new();
+        for (a, (ax, ay)) in positions.iter().enumerate() {
+            for (b, (bx, by)) in positions.iter().enumerate().skip(a + 1) {
+                let distance =
+                    ((ax - bx) * (ax - bx) + (ay - by) * (ay - by)).sqrt();
+                graph.add(
+                    &key(a as u8),
+                    &key(b as u8),
+                    (distance * 10.0) as u64,
+                );
+            }
+        }
+        // The fifth peer sits next to peer 0 but claims to be far from
+        // peers 1 and 2
+        graph.add(&key(4), &key(0), 10);
+        graph.add(&key(4), &key(3), 510);
+        let lie = liar_detour.unwrap_or(0);
+        graph.add(&key(4), &key(1), 310 + lie);
+        graph.add(&key(4), &key(2), 410 + lie);
+        graph
+    }
+
+    #[test]
+    fn honest_peers_are_consistent() {
+        let report = TriangleCheck::default().check(&graph(None));
+        assert!(report.violations.is_empty());
+        assert!(report.inconsistent_edges.is_empty());
+        assert_eq!(report.suspicious().count(), 0);
+        assert_eq!(report.scores.len(), 5);
+    }
+
+    #[test]
+    fn lying_peer_is_the_most_suspicious() {
+        let report = TriangleCheck::default().check(&graph(Some(1000)));
+        assert!(!report.violations.is_empty());
+        for violation in report.violations.iter() {
+            assert!(violation.edge == (1, 4) || violation.edge == (2, 4));
+        }
+        assert_eq!(report.scores[0].peer, key(4));
+        assert!(report.scores[0].suspicious);
+        assert_eq!(report.suspicious().count(), 1);
+        assert_eq!(report.score_of(&key(3)).unwrap().violations, 0);
+    }
+
+    #[test]
+    fn lowest_latency_of_a_pair_is_kept() {
+        let mut graph = LatencyGraph::new();
+        graph.add(&key(0), &key(1), 50);
+        graph.add(&key(1), &key(0), 30);
+        graph.add(&key(0), &key(1), 40);
+        graph.add(&key(0), &key(0), 10);
+        assert_eq!(graph.latency(1, 0), Some(30));
+        assert_eq!(graph.latency(0, 0), None);
+        assert_eq!(graph.peers().len(), 2);
+    }
+
+    #[test]
+    fn huge_latencies_dont_overflow_the_detour() {
+        let mut graph = LatencyGraph::new();
+        graph.add(&key(0), &key(1), u64::MAX);
+        graph.add(&key(1), &key(2), 2);
+        graph.add(&key(0), &key(2), 10);
+        // Only the huge edge itself is longer than its detour
+        let report = TriangleCheck::default().check(&graph);
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].edge, (0, 1));
+    }
+}
@@ -0,0 +1,71 @@
+//! rkyv archives of the values sent between peers and kept in sled. Archives
+//! are validated before any of their fields are read.
+use bytecheck::CheckBytes;
+use rkyv::de::deserializers::AllocDeserializer;
+use rkyv::ser::serializers::AlignedSerializer;
+use rkyv::ser::Serializer;
+use rkyv::validation::DefaultArchiveValidator;
+use rkyv::{check_archived_root, AlignedVec, Archive, Deserialize, Serialize};
+use std::mem;
+
+/// Archives the value with rkyv
+pub(crate) fn encode<T>(value: &T) -> AlignedVec
+where
+    T: Serialize<AlignedSerializer<AlignedVec>>,
+{
+    let mut serializer = AlignedSerializer::new(AlignedVec::new());
+    match serializer.serialize_value(value) {
+        Ok(_) => serializer.into_inner(),
+        Err(unreachable) => match unreachable {},
+    }
+}
+
+/// Reads a value archived by encode, checking the archive first
+pub(crate) fn decode<T: Archive>(bytes: &[u8]) -> Option<T>
+where
+    T::Archived:
+        CheckBytes<DefaultArchiveValidator> + Deserialize<T, AllocDeserializer>,
+{
+    if bytes.len() < mem::size_of::<T::Archived>() {
+        return None;
+    }
+    // Archives are read in place, so the bytes have to be aligned
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    let archived = check_archived_root::<T>(&aligned).ok()?;
+    match archived.deserialize(&mut AllocDeserializer) {
+        Ok(value) => Some(value),
+        Err(unreachable) => match unreachable {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::evaluation::DeserializableVDFResult;
+
+    fn result() -> DeserializableVDFResult {
+        DeserializableVDFResult {
+            result: String::from("4"),
+            iterations: 1,
+        }
+    }
+
+    #[test]
+    fn values_round_trip_through_their_archive() {
+        let archive = encode(&result());
+        assert_eq!(decode::<DeserializableVDFResult>(&archive), Some(result()));
+    }
+
+    #[test]
+    fn short_and_invalid_archives_are_refused() {
+        let archive = encode(&result());
+        assert_eq!(decode::<DeserializableVDFResult>(&archive[..3]), None);
+
+        // Point the relative pointer of the string somewhere else
+        let mut archive = archive.to_vec();
+        let last = archive.len() - 1;
+        archive[last - 11..=last].iter_mut().for_each(|b| *b = 0xff);
+        assert_eq!(decode::<DeserializableVDFResult>(&archive), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Network coordinates of the peers estimated with Vivaldi (Dabek et al.,
+//! 2004) from proven latencies only. Every peer gets a position on a plane
+//! and a height modelling its access link, so that the distance between two
+//! peers predicts the latency between them even if they never ran a session.
+use crate::analysis::LatencyGraph;
+use crate::error::PoLError;
+use crate::latency::LatencyProof;
+use crate::store::StoredProof;
+use crate::vdf::group::VdfGroup;
+
+/// Dimensions of the positions, not counting the height
+pub const DIMENSIONS: usize = 2;
+
+/// Weight of a sample in the error estimate of a peer
+pub const ERROR_GAIN: f64 = 0.25;
+
+/// Weight of a sample in the position of a peer
+pub const POSITION_GAIN: f64 = 0.25;
+
+/// Distance from the origin new peers start at, each in its own direction so
+/// that the peers don't move along a single line
+pub const START_RADIUS: f64 = 1.0;
+
+/// Angle between the directions of consecutive new peers
+const GOLDEN_ANGLE: f64 = 2.399_963_229_728_653;
+
+/// Heights are kept above this, so that no peer sits exactly on the plane
+pub const MIN_HEIGHT: f64 = 1e-3;
+
+/// Errors are kept above this, so that the weight of a sample is defined even
+/// once two peers predict each other exactly
+pub const MIN_ERROR: f64 = 1e-6;
+
+/// Position of a peer, in the same units as the latencies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinate {
+    pub position: [f64; DIMENSIONS],
+    pub height: f64,
+    /// Relative error of the coordinate, from 1 for a new peer towards 0
+    pub error: f64,
+}
+
+impl Default for Coordinate {
+    fn default() -> Self {
+        Self {
+            position: [0.0; DIMENSIONS],
+            height: MIN_HEIGHT,
+            error: 1.0,
+        }
+    }
+}
+
+impl Coordinate {
+    /// Predicted latency to the other peer, the distance on the plane plus
+    /// both heights
+    pub fn distance(&self, other: &Coordinate) -> f64 {
+        planar_distance(&self.position, &other.position)
+            + self.height
+            + other.height
+    }
+}
+
+fn planar_distance(a: &[f64; DIMENSIONS], b: &[f64; DIMENSIONS]) -> f64 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Vivaldi coordinates of the peers, updated incrementally with every
+/// latency observed. Observations are also kept in a LatencyGraph, so they
+/// can be replayed with refine until the coordinates settle.
+#[derive(Debug, Clone, Default)]
+pub struct Vivaldi {
+    samples: LatencyGraph,
+    coordinates: Vec<Coordinate>,
+}
+
+impl Vivaldi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verifies the proof in group and observes its latency
+    pub fn add_proof<G: VdfGroup>(
+        &mut self,
+        group: &G,
+        proof: &LatencyProof,
+    ) -> Result<u64, PoLError> {
+        let latency = proof.verify(group)?;
+        self.observe(&proof.prover_key, &proof.verifier_key, latency);
+        Ok(latency)
+    }
+
+    /// Observes a proof already verified by a ProofStore
+    pub fn add_stored(&mut self, stored: &StoredProof) {
+        self.observe(
+            &stored.proof.prover_key,
+            &stored.proof.verifier_key,
+            stored.latency,
+        );
+    }
+
+    /// Moves both peers towards coordinates that predict the latency
+    pub fn observe(&mut self, peer: &[u8], other: &[u8], latency: u64) {
+        self.samples.add(peer, other, latency);
+        let (a, b) =
+            (self.samples.index_of(peer), self.samples.index_of(other));
+        if let (Some(a), Some(b)) = (a, b) {
+            // The graph keeps the tightest bound of the pair, which is also
+            // the one refine replays
+            let latency = match self.samples.latency(a, b) {
+                Some(latency) => latency,
+                None => return,
+            };
+            while self.coordinates.len() < self.samples.peers().len() {
+                let angle = GOLDEN_ANGLE * self.coordinates.len() as f64;
+                let mut start = Coordinate::default();
+                start.position[0] = START_RADIUS * angle.cos();
+                start.position[1] = START_RADIUS * angle.sin();
+                self.coordinates.push(start);
+            }
+            self.update(a, b, latency as f64);
+        }
+    }
+
+    /// Replays every latency observed so far rounds times
+    pub fn refine(&mut self, rounds: usize) {
+        let edges = self.samples.edges();
+        for _ in 0..rounds {
+            for (a, b, latency) in edges.iter() {
+                self.update(*a, *b, *latency as f64);
+            }
+        }
+    }
+
+    /// One Vivaldi step for both peers, from their coordinates before it
+    fn update(&mut self, a: usize, b: usize, latency: f64) {
+        if a == b || latency <= 0.0 {
+            return;
+        }
+        let (first, second) = (self.coordinates[a], self.coordinates[b]);
+        self.coordinates[a] = step(&first, &second, latency);
+        self.coordinates[b] = step(&second, &first, latency);
+    }
+
+    pub fn coordinate(&self, peer: &[u8]) -> Option<&Coordinate> {
+        self.coordinates.get(self.samples.index_of(peer)?)
+    }
+
+    /// Predicted latency between two peers, whether or not it was observed
+    pub fn predict(&self, peer: &[u8], other: &[u8]) -> Option<f64> {
+        Some(self.coordinate(peer)?.distance(self.coordinate(other)?))
+    }
+
+    /// Public keys of the peers with coordinates
+    pub fn peers(&self) -> &[Vec<u8>] {
+        self.samples.peers()
+    }
+}
+
+/// Moves own towards or away from other by the error of the prediction
+fn step(own: &Coordinate, other: &Coordinate, latency: f64) -> Coordinate {
+    let distance = own.distance(other);
+    let weight = own.error / (own.error + other.error);
+    let sample_error = (distance - latency).abs() / latency;
+    let error = sample_error * ERROR_GAIN * weight
+        + own.error * (1.0 - ERROR_GAIN * weight);
+
+    let planar = planar_distance(&own.position, &other.position);
+    let mut direction = [0.0; DIMENSIONS];
+    if planar > 0.0 {
+        for (i, axis) in direction.iter_mut().enumerate() {
+            *axis = (own.position[i] - other.position[i]) / distance;
+        }
+    }
+    let height_direction = (own.height + other.height) / distance;
+
+    let force = POSITION_GAIN * weight * (latency - distance);
+    let mut position = own.position;
+    for (axis, direction) in position.iter_mut().zip(direction.iter()) {
+        *axis += force * direction;
+    }
+    Coordinate {
+        position,
+        height: (own.height + force * height_direction).max(MIN_HEIGHT),
+        error: error.clamp(MIN_ERROR, 1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(peer: u8) -> Vec<u8> {
+        vec![peer; 32]
+    }
+
+    /// Latency between peers on a plane, a hundred times their distance
+    fn latency(a: (f64, f64), b: (f64, f64)) -> u64 {
+        (((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt() * 100.0) as u64
+    }
+
+    #[test]
+    fn unobserved_latency_is_predicted() {
+        let positions =
+            [(0.0, 0.0), (4.0, 0.0), (0.0, 3.0), (4.0, 3.0), (2.0, 6.0)];
+        let mut vivaldi = Vivaldi::new();
+        for (a, first) in positions.iter().enumerate() {
+            for (b, second) in positions.iter().enumerate().skip(a + 1) {
+                // Peers 0 and 4 never run a session
+                if (a, b) != (0, 4) {
+                    vivaldi.observe(
+                        &key(a as u8),
+                        &key(b as u8),
+                        latency(*first, *second),
+                    );
+                }
+            }
+        }
+        vivaldi.refine(500);
+
+        let actual = latency(positions[0], positions[4]) as f64;
+        let predicted = vivaldi.predict(&key(0), &key(4)).unwrap();
+        assert!(
+            (predicted - actual).abs() < actual * 0.15,
+            "{} {}",
+            predicted,
+            actual
+        );
+        assert!(vivaldi.coordinate(&key(2)).unwrap().error < 0.1);
+        assert_eq!(vivaldi.predict(&key(0), &key(5)), None);
+    }
+
+    #[test]
+    fn first_observation_separates_the_peers() {
+        let mut vivaldi = Vivaldi::new();
+        vivaldi.observe(&key(0), &key(1), 100);
+        let predicted = vivaldi.predict(&key(0), &key(1)).unwrap();
+        assert!(predicted > 0.0 && predicted <= 100.0);
+        assert_eq!(vivaldi.peers().len(), 2);
+    }
+
+    #[test]
+    fn looser_bounds_move_the_peers_as_the_tightest_one() {
+        let mut tight = Vivaldi::new();
+        tight.observe(&key(0), &key(1), 100);
+        tight.observe(&key(0), &key(1), 100);
+        let mut loose = Vivaldi::new();
+        loose.observe(&key(0), &key(1), 100);
+        loose.observe(&key(0), &key(1), 1000);
+        assert_eq!(loose.coordinate(&key(0)), tight.coordinate(&key(0)));
+        assert_eq!(loose.coordinate(&key(1)), tight.coordinate(&key(1)));
+    }
+
+    #[test]
+    fn exact_predictions_keep_the_coordinates_finite() {
+        let own = Coordinate {
+            position: [0.0, 0.0],
+            height: MIN_HEIGHT,
+            error: MIN_ERROR,
+        };
+        let other = Coordinate {
+            position: [100.0, 0.0],
+            ..own
+        };
+        let latency = own.distance(&other);
+        let moved = step(&own, &other, latency);
+        assert!(moved.error >= MIN_ERROR);
+        assert!(moved.position.iter().all(|axis| axis.is_finite()));
+        assert!(moved.height.is_finite());
+    }
+}
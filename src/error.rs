@@ -0,0 +1,204 @@
+use crate::vdf::bigint::{Int, Verification};
+use crate::{MessageKind, PoLStartError};
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+/// Everything that can go wrong in calculating a Proof of Latency. Errors
+/// caused by the other peer are sent back to it in PoLMessage::Abort, so they
+/// need to be serializable.
+#[derive(Archive, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[archive(derive(CheckBytes))]
+pub enum PoLError {
+    /// A number or group received from the other peer couldn't be parsed
+    Parse { input: String },
+    /// The cap is not a prime
+    InvalidCap,
+    /// A value is not an element of the group in use
+    InvalidGroupElement,
+    /// A VDF was evaluated in another group than the one in use
+    InvalidGroup,
+    /// A generator part is not a positive integer, which would let a peer
+    /// fix the shared generator
+    InvalidGeneratorPart,
+    /// A VDF proof didn't verify
+    InvalidProof,
+    /// A signature didn't verify or was made by someone else than the peer
+    InvalidSignature,
+    /// The other peer sent a message that doesn't belong in the current state
+    ProtocolViolation {
+        expected: MessageKind,
+        received: MessageKind,
+    },
+    /// A frame received from or sent to the other peer is larger than
+    /// allowed
+    FrameTooLarge { size: u64, max: u64 },
+    /// The other peer didn't answer in time
+    Timeout,
+    /// The channel to the other peer or to the VDF was closed
+    ChannelClosed,
+    /// The VDF was cancelled before it produced a proof
+    Cancelled,
+    /// The proof type can't be calculated with the options of the VDF
+    UnsupportedProofType,
+    /// The checkpoints to resume from were made for another VDF statement
+    CheckpointMismatch,
+    /// The other peer aborted the Proof of Latency with its own error, kept
+    /// as its kind and description so that errors never contain errors
+    PeerAborted { kind: ErrorKind, reason: String },
+    /// The Proof of Latency was started without a group, an upper bound or a
+    /// connection
+    NotStarted,
+    /// A request of a coordinator was made too long ago or was received
+    /// before, or a reply answers another request
+    Replayed,
+}
+
+impl fmt::Display for PoLError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoLError::Parse { input } => {
+                write!(f, "Couldn't parse {:?}", input)
+            }
+            PoLError::InvalidCap => write!(f, "Invalid cap value encountered!"),
+            PoLError::InvalidGroupElement => {
+                write!(f, "Value is not an element of the group")
+            }
+            PoLError::InvalidGroup => {
+                write!(f, "VDF was evaluated in another group")
+            }
+            PoLError::InvalidGeneratorPart => {
+                write!(f, "Generator part is not a positive integer")
+            }
+            PoLError::InvalidProof => write!(f, "VDF proof was incorrect"),
+            PoLError::InvalidSignature => write!(f, "Invalid signature"),
+            PoLError::ProtocolViolation { expected, received } => write!(
+                f,
+                "Expected PoLMessage::{:?}, received PoLMessage::{:?}",
+                expected, received
+            ),
+            PoLError::FrameTooLarge { size, max } => write!(
+                f,
+                "Frame of {} bytes is larger than the maximum of {} bytes",
+                size, max
+            ),
+            PoLError::Timeout => write!(f, "Timed out waiting for the peer"),
+            PoLError::ChannelClosed => write!(f, "Channel closed"),
+            PoLError::Cancelled => write!(f, "VDF was cancelled"),
+            PoLError::UnsupportedProofType => {
+                write!(f, "Proof type not supported with these VDF options")
+            }
+            PoLError::CheckpointMismatch => {
+                write!(f, "Checkpoints belong to another VDF statement")
+            }
+            PoLError::PeerAborted { reason, .. } => {
+                write!(f, "The peer aborted the Proof of Latency: {}", reason)
+            }
+            PoLError::NotStarted => write!(f, "{}", PoLStartError),
+            PoLError::Replayed => {
+                write!(f, "Request is too old or was made before")
+            }
+        }
+    }
+}
+
+impl Error for PoLError {}
+
+impl From<PoLStartError> for PoLError {
+    fn from(_: PoLStartError) -> Self {
+        PoLError::NotStarted
+    }
+}
+
+/// The kind of a PoLError without its contents, for reporting the error the
+/// other peer aborted with
+#[derive(
+    Archive, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[archive(derive(CheckBytes))]
+pub enum ErrorKind {
+    Parse,
+    InvalidCap,
+    InvalidGroupElement,
+    InvalidGroup,
+    InvalidGeneratorPart,
+    InvalidProof,
+    InvalidSignature,
+    ProtocolViolation,
+    FrameTooLarge,
+    Timeout,
+    ChannelClosed,
+    Cancelled,
+    UnsupportedProofType,
+    CheckpointMismatch,
+    PeerAborted,
+    NotStarted,
+    Replayed,
+}
+
+impl PoLError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PoLError::Parse { .. } => ErrorKind::Parse,
+            PoLError::InvalidCap => ErrorKind::InvalidCap,
+            PoLError::InvalidGroupElement => ErrorKind::InvalidGroupElement,
+            PoLError::InvalidGroup => ErrorKind::InvalidGroup,
+            PoLError::InvalidGeneratorPart => ErrorKind::InvalidGeneratorPart,
+            PoLError::InvalidProof => ErrorKind::InvalidProof,
+            PoLError::InvalidSignature => ErrorKind::InvalidSignature,
+            PoLError::ProtocolViolation { .. } => ErrorKind::ProtocolViolation,
+            PoLError::FrameTooLarge { .. } => ErrorKind::FrameTooLarge,
+            PoLError::Timeout => ErrorKind::Timeout,
+            PoLError::ChannelClosed => ErrorKind::ChannelClosed,
+            PoLError::Cancelled => ErrorKind::Cancelled,
+            PoLError::UnsupportedProofType => ErrorKind::UnsupportedProofType,
+            PoLError::CheckpointMismatch => ErrorKind::CheckpointMismatch,
+            PoLError::PeerAborted { .. } => ErrorKind::PeerAborted,
+            PoLError::NotStarted => ErrorKind::NotStarted,
+            PoLError::Replayed => ErrorKind::Replayed,
+        }
+    }
+
+    /// The error the peer aborted with, as it is returned on our side. An
+    /// abort the peer only relays is kept as it is, so relaying never nests.
+    pub fn aborted_by_peer(error: PoLError) -> Self {
+        match error {
+            PoLError::PeerAborted { .. } => error,
+            error => PoLError::PeerAborted {
+                kind: error.kind(),
+                reason: error.to_string(),
+            },
+        }
+    }
+}
+
+/// Parses a decimal integer sent by the other peer
+pub fn parse_int(s: &str) -> Result<Int, PoLError> {
+    // Only the beginning of the input is echoed back to the peer
+    Int::from_str_radix(s, 10).map_err(|_| PoLError::Parse {
+        input: s.chars().take(64).collect(),
+    })
+}
+
+/// Parses a generator part sent by the other peer, checking that it is
+/// positive. The parts are multiplied together, so a zero part would make the
+/// shared generator the same in every session.
+pub fn parse_generator_part(s: &str) -> Result<Int, PoLError> {
+    let part = parse_int(s)?;
+    if part > Int::zero() {
+        Ok(part)
+    } else {
+        Err(PoLError::InvalidGeneratorPart)
+    }
+}
+
+/// Parses a cap sent by the other peer, checking that it is a prime
+pub fn parse_cap(s: &str) -> Result<Int, PoLError> {
+    let cap = parse_int(s)?;
+    if cap > Int::one() && Verification::verify_prime(cap.clone()) {
+        Ok(cap)
+    } else {
+        Err(PoLError::InvalidCap)
+    }
+}
@@ -0,0 +1,251 @@
+//! Ed25519 identities of the peers. Every PoLMessage is sent inside a
+//! SignedPoLMessage, and the finished Proof of Latency is signed by both the
+//! prover and the verifier, binding the two proofs to both identities.
+use crate::error::PoLError;
+use crate::vdf::proof::DeserializableVDFProof;
+use crate::PoLMessage;
+use bytecheck::CheckBytes;
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+use rand::rngs::OsRng;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Domain separator of everything signed in this protocol
+const DOMAIN: &[u8] = b"proof_of_latency/v1";
+
+/// An Ed25519 keypair identifying a peer
+pub struct Identity {
+    keypair: Keypair,
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        Self {
+            keypair: Keypair::generate(&mut OsRng),
+        }
+    }
+
+    /// Restores an identity from the 64 bytes returned by to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PoLError> {
+        Keypair::from_bytes(bytes)
+            .map(|keypair| Self { keypair })
+            .map_err(|_| PoLError::Parse {
+                input: String::from("keypair"),
+            })
+    }
+
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.keypair.to_bytes()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.keypair.public
+    }
+
+    pub fn sign(&self, digest: &[u8]) -> Vec<u8> {
+        self.keypair.sign(digest).to_bytes().to_vec()
+    }
+}
+
+// Keypair isn't Clone, so the copy is restored from its bytes
+impl Clone for Identity {
+    fn clone(&self) -> Self {
+        match Keypair::from_bytes(&self.keypair.to_bytes()) {
+            Ok(keypair) => Self { keypair },
+            Err(_) => unreachable!("A keypair restores from its own bytes"),
+        }
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("public_key", &self.keypair.public)
+            .finish()
+    }
+}
+
+/// Checks that signature is a signature of digest by public_key, returning the
+/// parsed public key
+pub fn verify_signature(
+    public_key: &[u8],
+    digest: &[u8],
+    signature: &[u8],
+) -> Result<PublicKey, PoLError> {
+    let public_key = PublicKey::from_bytes(public_key)
+        .map_err(|_| PoLError::InvalidSignature)?;
+    let signature = Signature::try_from(signature)
+        .map_err(|_| PoLError::InvalidSignature)?;
+    public_key
+        .verify(digest, &signature)
+        .map_err(|_| PoLError::InvalidSignature)?;
+    Ok(public_key)
+}
+
+/// Canonical hashing of signed data. Every field is length prefixed, so two
+/// different messages never hash the same bytes.
+pub(crate) struct Transcript(blake3::Hasher);
+
+impl Transcript {
+    pub(crate) fn new(label: &str) -> Self {
+        let mut transcript = Transcript(blake3::Hasher::new());
+        transcript.field(DOMAIN).field(label.as_bytes());
+        transcript
+    }
+
+    pub(crate) fn field(&mut self, bytes: &[u8]) -> &mut Self {
+        self.0.update(&(bytes.len() as u64).to_be_bytes());
+        self.0.update(bytes);
+        self
+    }
+
+    pub(crate) fn proof(
+        &mut self,
+        proof: &DeserializableVDFProof,
+    ) -> &mut Self {
+        self.field(proof.group.as_bytes())
+            .field(proof.generator.as_bytes())
+            .field(proof.output.result.as_bytes())
+            .field(&proof.output.iterations.to_be_bytes())
+            .field(proof.cap.as_bytes())
+            .field(proof.pi.as_bytes())
+            .field(&(proof.mu.len() as u64).to_be_bytes());
+        for mu in proof.mu.iter() {
+            self.field(mu.as_bytes());
+        }
+        self.field(format!("{:?}", proof.proof_type).as_bytes())
+            .field(format!("{:?}", proof.challenge).as_bytes())
+    }
+
+    pub(crate) fn finish(&self) -> [u8; 32] {
+        *self.0.finalize().as_bytes()
+    }
+}
+
+/// The digest of a message that its sender signs
+pub fn message_digest(message: &PoLMessage) -> [u8; 32] {
+    let mut transcript = Transcript::new(&format!("{:?}", message.kind()));
+    match message {
+        PoLMessage::GeneratorPart { num } | PoLMessage::Cap { num } => {
+            transcript.field(num.as_bytes());
+        }
+        PoLMessage::GeneratorPartAndCap {
+            generator_part,
+            cap,
+        } => {
+            transcript
+                .field(generator_part.as_bytes())
+                .field(cap.as_bytes());
+        }
+        PoLMessage::VDFResult { result } => {
+            transcript
+                .field(result.result.as_bytes())
+                .field(&result.iterations.to_be_bytes());
+        }
+        PoLMessage::VDFProof { proof } => {
+            transcript.proof(proof);
+        }
+        PoLMessage::VDFProofAndCap { proof, cap } => {
+            transcript.proof(proof).field(cap.as_bytes());
+        }
+        PoLMessage::ProofOfLatency { proof } => {
+            transcript
+                .field(&proof.digest())
+                .field(&proof.prover_signature)
+                .field(&proof.verifier_signature);
+        }
+        PoLMessage::Abort { error } => {
+            transcript.field(format!("{:?}", error).as_bytes());
+        }
+        PoLMessage::ProveTo {
+            address,
+            verifier,
+            upper_bound,
+            nonce,
+            timestamp,
+        } => {
+            transcript
+                .field(address.as_bytes())
+                .field(verifier)
+                .field(&upper_bound.to_be_bytes())
+                .field(&nonce.to_be_bytes())
+                .field(&timestamp.to_be_bytes());
+        }
+        PoLMessage::ProvedTo { nonce, proof } => {
+            transcript
+                .field(&nonce.to_be_bytes())
+                .field(&proof.digest())
+                .field(&proof.prover_signature)
+                .field(&proof.verifier_signature);
+        }
+        PoLMessage::ProveToFailed { nonce, error } => {
+            transcript
+                .field(&nonce.to_be_bytes())
+                .field(format!("{:?}", error).as_bytes());
+        }
+    }
+    transcript.finish()
+}
+
+/// A PoLMessage signed by its sender
+#[derive(Archive, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[archive(derive(CheckBytes))]
+pub struct SignedPoLMessage {
+    pub message: PoLMessage,
+    pub signer: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedPoLMessage {
+    pub fn new(message: PoLMessage, identity: &Identity) -> Self {
+        let signature = identity.sign(&message_digest(&message));
+        Self {
+            message,
+            signer: identity.public_key().to_bytes().to_vec(),
+            signature,
+        }
+    }
+
+    /// Checks the signature of the message, returning the signer
+    pub fn verify(&self) -> Result<PublicKey, PoLError> {
+        verify_signature(
+            &self.signer,
+            &message_digest(&self.message),
+            &self.signature,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_message_verifies_only_unmodified() {
+        let identity = Identity::generate();
+        let mut signed = SignedPoLMessage::new(
+            PoLMessage::GeneratorPartAndCap {
+                generator_part: String::from("12"),
+                cap: String::from("7"),
+            },
+            &identity,
+        );
+        assert_eq!(signed.verify(), Ok(identity.public_key()));
+
+        // Moving a digit from one field to the other changes the digest
+        signed.message = PoLMessage::GeneratorPartAndCap {
+            generator_part: String::from("1"),
+            cap: String::from("27"),
+        };
+        assert_eq!(signed.verify(), Err(PoLError::InvalidSignature));
+    }
+
+    #[test]
+    fn identity_survives_a_round_trip() {
+        let identity = Identity::generate();
+        let restored = Identity::from_bytes(&identity.to_bytes()).unwrap();
+        assert_eq!(restored.public_key(), identity.public_key());
+        assert!(Identity::from_bytes(&[0u8; 3]).is_err());
+    }
+}
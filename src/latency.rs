@@ -0,0 +1,206 @@
+//! The end product of the protocol, a Proof of Latency that anyone can verify
+//! without having taken part in it.
+use crate::error::{self, PoLError};
+use crate::identity::{self, Identity, Transcript};
+use crate::vdf::calibration::{Calibration, Confidence, TimeBound};
+use crate::vdf::group::VdfGroup;
+use crate::vdf::proof::{
+    ChallengeType, DeserializableVDFProof, ProofType, VDFProof,
+};
+use crate::{combine_generator_parts, PoLRole};
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// Version of the protocol the LatencyProof was created with
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Both VDF proofs of a Proof of Latency with everything that binds them
+/// together: the generator parts g1 and g2 the shared generator is derived
+/// from, the caps l1 and l2 that stopped each other's VDFs, and the
+/// signatures of both peers. The verifier's calibration, if it has one,
+/// converts the latency to milliseconds.
+#[derive(
+    Archive, Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[archive(derive(CheckBytes))]
+pub struct LatencyProof {
+    pub version: u32,
+    pub prover: DeserializableVDFProof,
+    pub verifier: DeserializableVDFProof,
+    /// g1 and l1, created by the prover
+    pub prover_generator_part: String,
+    pub prover_cap: String,
+    /// g2 and l2, created by the verifier
+    pub verifier_generator_part: String,
+    pub verifier_cap: String,
+    pub prover_key: Vec<u8>,
+    pub verifier_key: Vec<u8>,
+    pub calibration: Option<Calibration>,
+    pub prover_signature: Vec<u8>,
+    pub verifier_signature: Vec<u8>,
+}
+
+impl LatencyProof {
+    /// The digest both peers sign, covering everything but the signatures
+    pub fn digest(&self) -> [u8; 32] {
+        let mut transcript = Transcript::new("LatencyProof");
+        transcript
+            .field(&self.version.to_be_bytes())
+            .proof(&self.prover)
+            .proof(&self.verifier)
+            .field(self.prover_generator_part.as_bytes())
+            .field(self.prover_cap.as_bytes())
+            .field(self.verifier_generator_part.as_bytes())
+            .field(self.verifier_cap.as_bytes())
+            .field(&self.prover_key)
+            .field(&self.verifier_key);
+        if let Some(calibration) = &self.calibration {
+            transcript
+                .field(&calibration.bit_length.to_be_bytes())
+                .field(&calibration.sample_ms.to_be_bytes())
+                .field(&(calibration.samples.len() as u64).to_be_bytes());
+            for sample in calibration.samples.iter() {
+                transcript.field(&sample.to_be_bytes());
+            }
+        }
+        transcript.finish()
+    }
+
+    /// Signs the proof as role
+    pub fn sign(&mut self, identity: &Identity, role: &PoLRole) {
+        let signature = identity.sign(&self.digest());
+        match role {
+            PoLRole::Prover => self.prover_signature = signature,
+            PoLRole::Verifier => self.verifier_signature = signature,
+        }
+    }
+
+    /// Checks the signature of role
+    pub fn verify_signature(&self, role: &PoLRole) -> Result<(), PoLError> {
+        let (key, signature) = match role {
+            PoLRole::Prover => (&self.prover_key, &self.prover_signature),
+            PoLRole::Verifier => (&self.verifier_key, &self.verifier_signature),
+        };
+        identity::verify_signature(key, &self.digest(), signature).map(|_| ())
+    }
+
+    /// Checks everything except for the signatures, returning the proven
+    /// latency bound in iterations. Both VDFs have to be evaluated in group,
+    /// as the peers could otherwise pick a group they can shortcut. The
+    /// verifier evaluates from receiving g1 until receiving the prover's
+    /// proof, and the prover from receiving g2 until its upper bound, so the
+    /// difference is an upper bound for the round trip time between the
+    /// peers.
+    pub fn verify_bindings<G: VdfGroup>(
+        &self,
+        group: &G,
+    ) -> Result<u64, PoLError> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(PoLError::Parse {
+                input: format!("version {}", self.version),
+            });
+        }
+        let encoded = group.encode_group();
+        if self.prover.group != encoded || self.verifier.group != encoded {
+            return Err(PoLError::InvalidGroup);
+        }
+        let prover = VDFProof::<G>::try_from(&self.prover)?;
+        let verifier = VDFProof::<G>::try_from(&self.verifier)?;
+
+        // Both VDFs are evaluated from the generator both peers contributed to
+        let generator = combine_generator_parts(
+            group,
+            &error::parse_generator_part(&self.prover_generator_part)?,
+            &error::parse_generator_part(&self.verifier_generator_part)?,
+        );
+        if prover.generator != generator || verifier.generator != generator {
+            return Err(PoLError::InvalidProof);
+        }
+
+        // Each VDF is proven with the cap the other peer created, in a
+        // Wesolowski proof, as Pietrzak proofs don't use the cap
+        if prover.challenge != ChallengeType::Interactive
+            || verifier.challenge != ChallengeType::Interactive
+            || prover.proof_type == ProofType::Pietrzak
+            || verifier.proof_type == ProofType::Pietrzak
+            || prover.cap != error::parse_cap(&self.verifier_cap)?
+            || verifier.cap != error::parse_cap(&self.prover_cap)?
+        {
+            return Err(PoLError::InvalidCap);
+        }
+
+        if !prover.verify() || !verifier.verify() {
+            return Err(PoLError::InvalidProof);
+        }
+
+        // A calibration only applies to the group it was measured in
+        if let Some(calibration) = &self.calibration {
+            if calibration.bit_length != group.bit_length() {
+                return Err(PoLError::InvalidProof);
+            }
+        }
+
+        // The verifier started evaluating first and stopped last
+        verifier
+            .output
+            .iterations
+            .checked_sub(prover.output.iterations)
+            .ok_or(PoLError::InvalidProof)
+    }
+
+    /// Verifies the whole proof in group, returning the proven latency bound
+    /// in iterations
+    pub fn verify<G: VdfGroup>(&self, group: &G) -> Result<u64, PoLError> {
+        let latency = self.verify_bindings(group)?;
+        self.verify_signature(&PoLRole::Prover)?;
+        self.verify_signature(&PoLRole::Verifier)?;
+        Ok(latency)
+    }
+
+    /// Converts a latency returned by verify to milliseconds with the
+    /// calibration of the verifier
+    pub fn time_bound(
+        &self,
+        latency: u64,
+        confidence: Confidence,
+    ) -> Option<TimeBound> {
+        self.calibration
+            .as_ref()
+            .map(|calibration| calibration.time_bound(latency, confidence))
+    }
+}
+
+/// An unverified proof between two made up keys, for tests that only store
+/// or hash proofs. Validation of rkyv archives refuses several empty strings,
+/// so every field is filled in.
+#[cfg(test)]
+pub(crate) fn made_up_proof(prover: u8, verifier: u8) -> LatencyProof {
+    use crate::vdf::evaluation::DeserializableVDFResult;
+    use ed25519_dalek::{PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+
+    let vdf = DeserializableVDFProof {
+        group: String::from("7"),
+        generator: String::from("2"),
+        output: DeserializableVDFResult {
+            result: String::from("4"),
+            iterations: 1,
+        },
+        cap: String::from("3"),
+        pi: String::from("1"),
+        ..DeserializableVDFProof::default()
+    };
+    LatencyProof {
+        prover: vdf.clone(),
+        verifier: vdf,
+        prover_generator_part: String::from("5"),
+        prover_cap: String::from("3"),
+        verifier_generator_part: String::from("6"),
+        verifier_cap: String::from("3"),
+        prover_key: vec![prover; PUBLIC_KEY_LENGTH],
+        verifier_key: vec![verifier; PUBLIC_KEY_LENGTH],
+        prover_signature: vec![prover; SIGNATURE_LENGTH],
+        verifier_signature: vec![verifier; SIGNATURE_LENGTH],
+        ..LatencyProof::default()
+    }
+}
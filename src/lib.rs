@@ -1,30 +1,54 @@
-#![feature(test)]
+#![cfg_attr(feature = "nightly", feature(test))]
 #[macro_use]
 extern crate log;
 extern crate lazy_static;
 extern crate sm;
+#[cfg(feature = "nightly")]
 extern crate test;
 
-use ramp::Int;
-use ramp_primes::Generator;
+use crate::vdf::bigint::Generator;
+use crate::vdf::bigint::Int;
 
 use std::error::Error;
 use std::fmt;
+use std::time::{Duration, Instant};
 
 use crossbeam::channel::unbounded;
 use crossbeam::channel::{Receiver, Sender};
-use std::thread;
+use futures::channel::mpsc;
+use futures::future::Future;
+use futures::sink::Sink;
+use futures::stream::{Stream, StreamExt};
+use std::thread::{self, JoinHandle};
 
+use bytecheck::CheckBytes;
 use rkyv::{Archive, Deserialize, Serialize};
 
 // Internal imports
 // pub mod p2p;
+pub mod analysis;
+mod archive;
+pub mod coordinates;
+pub mod error;
+pub mod identity;
+pub mod latency;
+pub mod matrix;
+pub mod merkle;
+pub mod node;
+pub mod store;
+pub mod transport;
 pub mod vdf;
 use crate::PoL::*;
+use ed25519_dalek::PublicKey;
+use error::PoLError;
+use identity::{Identity, SignedPoLMessage};
+use latency::LatencyProof;
 use sm::sm;
-use vdf::evaluation::{DeserializableVDFResult, VDF};
+use transport::{ChannelTransport, Connection, Transport};
+use vdf::calibration::{Calibration, Confidence};
+use vdf::evaluation::{Cancellation, DeserializableVDFResult, VDF};
+use vdf::group::{RsaGroup, VdfGroup};
 use vdf::proof::{DeserializableVDFProof, VDFProof};
-use vdf::InvalidCapError;
 
 // RSA-2048, copied from Wikipedia
 pub const RSA_2048: &str = "2519590847565789349402718324004839857142928212620403202777713783604366202070759555626401852588078440691829064124951508218929855914917618450280848912007284499268739280728777673597141834727026189637501497182469116507761337985909570009733045974880842840179742910064245869181719511874612151517265463228221686998754918242243363725908514186546204357679842338718477444792073993423658482382428119816381501067481045166037730605620161967625613384414360383390441495263443219011465754445417842402092461651572335077870774981712577246796292638635637328991215483143816789988504044536402352738195137863656439121201039712282120720357";
@@ -86,7 +110,11 @@ pub enum PoLRole {
 
 /// All possible messages that are passed between the prover and the verifier in
 /// calculating a Proof of Latency
+// Messages are moved around a few times per protocol run, so the size of the
+// finished Proof of Latency doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Archive, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[archive(derive(CheckBytes))]
 pub enum PoLMessage {
     GeneratorPart {
         num: String,
@@ -114,14 +142,151 @@ pub enum PoLMessage {
         cap: String,
     },
 
+    /// The finished Proof of Latency, first signed by the verifier and then
+    /// by the prover
     ProofOfLatency {
-        prover: DeserializableVDFProof,
-        verifier: DeserializableVDFProof,
+        proof: LatencyProof,
     },
 
-    Error {
-        reason: String,
+    /// Aborts the Proof of Latency with the error the sender ran into
+    Abort {
+        error: PoLError,
     },
+
+    /// Asks a peer to prove its latency to the verifier at address, sent by
+    /// the coordinator of a latency matrix. The nonce is new for every
+    /// request and the timestamp in Unix milliseconds, so that the peer can
+    /// refuse replayed requests. The peer answers with ProvedTo or
+    /// ProveToFailed, echoing the nonce.
+    ProveTo {
+        address: String,
+        verifier: Vec<u8>,
+        upper_bound: u64,
+        nonce: u64,
+        timestamp: u64,
+    },
+
+    /// The Proof of Latency a peer ran for the ProveTo with the nonce
+    ProvedTo {
+        nonce: u64,
+        proof: LatencyProof,
+    },
+
+    /// The error the ProveTo with the nonce ended in
+    ProveToFailed {
+        nonce: u64,
+        error: PoLError,
+    },
+}
+
+/// The kind of a PoLMessage without its contents, for reporting protocol
+/// violations
+#[derive(
+    Archive, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[archive(derive(CheckBytes))]
+pub enum MessageKind {
+    GeneratorPart,
+    Cap,
+    GeneratorPartAndCap,
+    VDFResult,
+    VDFProof,
+    VDFProofAndCap,
+    ProofOfLatency,
+    Abort,
+    ProveTo,
+    ProvedTo,
+    ProveToFailed,
+}
+
+impl PoLMessage {
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            PoLMessage::GeneratorPart { .. } => MessageKind::GeneratorPart,
+            PoLMessage::Cap { .. } => MessageKind::Cap,
+            PoLMessage::GeneratorPartAndCap { .. } => {
+                MessageKind::GeneratorPartAndCap
+            }
+            PoLMessage::VDFResult { .. } => MessageKind::VDFResult,
+            PoLMessage::VDFProof { .. } => MessageKind::VDFProof,
+            PoLMessage::VDFProofAndCap { .. } => MessageKind::VDFProofAndCap,
+            PoLMessage::ProofOfLatency { .. } => MessageKind::ProofOfLatency,
+            PoLMessage::Abort { .. } => MessageKind::Abort,
+            PoLMessage::ProveTo { .. } => MessageKind::ProveTo,
+            PoLMessage::ProvedTo { .. } => MessageKind::ProvedTo,
+            PoLMessage::ProveToFailed { .. } => MessageKind::ProveToFailed,
+        }
+    }
+}
+
+/// Derives the generator both peers evaluate their VDFs from, the order of the
+/// parts not mattering
+pub fn combine_generator_parts<G: VdfGroup>(
+    group: &G,
+    a: &Int,
+    b: &Int,
+) -> G::Element {
+    let mul_str: String = (a * b).to_str_radix(16, true);
+    group.hash_to_element(&mul_str)
+}
+
+/// How long each state of the protocol may take before the Proof of Latency
+/// is aborted with PoLError::Timeout. States without a timeout wait forever,
+/// so None has to be set explicitly for a state to opt out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Verifier waiting for the generator part of the prover
+    pub generator_part: Option<Duration>,
+    /// Prover waiting for the generator part and the cap of the verifier
+    pub generator_part_and_cap: Option<Duration>,
+    /// Prover evaluating its VDF and calculating the proof
+    pub evaluation: Option<Duration>,
+    /// Verifier waiting for the VDF proof and the cap of the prover
+    pub vdf_proof_and_cap: Option<Duration>,
+    /// Both waiting for the Proof of Latency signed by the other
+    pub proof_of_latency: Option<Duration>,
+}
+
+/// Timeout of the states waiting for a message the peer can send right away
+pub const DEFAULT_MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout of the states waiting for a VDF to be evaluated and proven
+pub const DEFAULT_EVALUATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            generator_part: Some(DEFAULT_MESSAGE_TIMEOUT),
+            generator_part_and_cap: Some(DEFAULT_MESSAGE_TIMEOUT),
+            evaluation: Some(DEFAULT_EVALUATION_TIMEOUT),
+            vdf_proof_and_cap: Some(DEFAULT_EVALUATION_TIMEOUT),
+            proof_of_latency: Some(DEFAULT_MESSAGE_TIMEOUT),
+        }
+    }
+}
+
+impl Timeouts {
+    /// No timeouts, every state waiting forever
+    pub fn none() -> Self {
+        Self {
+            generator_part: None,
+            generator_part_and_cap: None,
+            evaluation: None,
+            vdf_proof_and_cap: None,
+            proof_of_latency: None,
+        }
+    }
+
+    /// The same timeout for every state
+    pub fn all(timeout: Duration) -> Self {
+        Self {
+            generator_part: Some(timeout),
+            generator_part_and_cap: Some(timeout),
+            evaluation: Some(timeout),
+            vdf_proof_and_cap: Some(timeout),
+            proof_of_latency: Some(timeout),
+        }
+    }
 }
 
 /// PoLStartError is thrown when Proof of Latency is started before all
@@ -131,364 +296,696 @@ pub struct PoLStartError;
 
 impl fmt::Display for PoLStartError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "I/O not defined yet, trying to start a Proof of Latency without new()?")
+        write!(f, "I/O not defined yet, trying to start a Proof of Latency without open_io(), open_async_io(), with_transport() or with_streams()?")
     }
 }
 
 impl Error for PoLStartError {
     fn description(&self) -> &str {
-        "I/O not defined yet, trying to start a Proof of Latency without new() and open_io()?"
+        "I/O not defined yet, trying to start a Proof of Latency without open_io(), open_async_io(), with_transport() or with_streams()?"
     }
 }
 
 /// Struct that keeps the needed variables in memory during the state machine
-/// execution modulus = N, generator = g
+/// execution, the VDFs being evaluated in the group G. generator = g
 #[derive(Debug)]
-pub struct ProofOfLatency {
+pub struct ProofOfLatency<G: VdfGroup = RsaGroup> {
     // Starting parameters
-    pub modulus: Option<Int>,
-    pub generator: Option<Int>,
-    pub upper_bound: Option<u32>,
-    // Channels for discussing with the VDF
+    pub group: Option<G>,
+    pub generator: Option<G::Element>,
+    pub upper_bound: Option<u64>,
+    // Speed of squarings on this machine, for reporting latency in time
+    pub calibration: Option<Calibration>,
+    // Identities, the peer being known after its first message if not set
+    identity: Option<Identity>,
+    peer: Option<PublicKey>,
+    // Deadlines of the states
+    pub timeouts: Timeouts,
+    // Channels for discussing with the VDF, and a handle cancelling it
     vdf_capper: Option<Sender<Int>>,
-    vdf_result_channel: Option<Receiver<Result<VDFProof, InvalidCapError>>>,
-    // User I/O
-    user_input_listener: Option<Receiver<PoLMessage>>,
-    user_output_sender: Option<Sender<PoLMessage>>,
+    vdf_result_channel:
+        Option<mpsc::UnboundedReceiver<Result<VDFProof<G>, PoLError>>>,
+    // Connection to the other peer
+    connection: Option<Connection>,
+    vdf_cancellation: Cancellation,
     // VDF Proofs
-    pub prover_result: Option<VDFProof>,
-    pub verifier_result: Option<VDFProof>,
+    pub prover_result: Option<VDFProof<G>>,
+    pub verifier_result: Option<VDFProof<G>>,
+    // The finished Proof of Latency, signed by us and possibly the peer
+    pub latency_proof: Option<LatencyProof>,
 }
 
-impl Default for ProofOfLatency {
+impl<G: VdfGroup> Default for ProofOfLatency<G> {
     fn default() -> Self {
         Self {
-            modulus: None,
+            group: None,
             generator: None,
             upper_bound: None,
+            calibration: None,
+            identity: None,
+            peer: None,
+            timeouts: Timeouts::default(),
             vdf_capper: None,
             vdf_result_channel: None,
+            vdf_cancellation: Cancellation::new(),
             prover_result: None,
             verifier_result: None,
-            user_input_listener: None,
-            user_output_sender: None,
+            latency_proof: None,
+            connection: None,
         }
     }
 }
 
-impl ProofOfLatency {
-    pub fn init(mut self, modulus: Int, upper_bound: u32) -> Self {
-        self.modulus = Some(modulus);
+impl<G: VdfGroup> ProofOfLatency<G> {
+    pub fn init(mut self, group: G, upper_bound: u64) -> Self {
+        self.group = Some(group);
         self.generator = None;
         self.upper_bound = Some(upper_bound);
         self
     }
 
-    pub fn open_io(&mut self) -> (Sender<PoLMessage>, Receiver<PoLMessage>) {
-        let (input, listener): (Sender<PoLMessage>, Receiver<PoLMessage>) =
-            unbounded();
-        let (sender, output): (Sender<PoLMessage>, Receiver<PoLMessage>) =
-            unbounded();
-        self.user_input_listener = Some(listener);
-        self.user_output_sender = Some(sender);
+    /// Sets the identity our messages are signed with. Without one, a new
+    /// identity is generated on start.
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Reports latency in milliseconds too, the verifier recording the
+    /// calibration in the Proof of Latency
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Aborts the Proof of Latency when a state takes longer than its timeout
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Only accepts messages signed by peer
+    pub fn with_peer(mut self, peer: PublicKey) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    /// Talks to the other peer through transport
+    pub fn with_transport<T: Transport + 'static>(
+        mut self,
+        transport: T,
+    ) -> Self {
+        self.connection = Some(Connection::Blocking(Box::new(transport)));
+        self
+    }
+
+    /// Uses an in-memory transport, returning the channels for relaying the
+    /// messages of the other peer
+    pub fn open_io(
+        &mut self,
+    ) -> (Sender<SignedPoLMessage>, Receiver<SignedPoLMessage>) {
+        let (input, listener): (
+            Sender<SignedPoLMessage>,
+            Receiver<SignedPoLMessage>,
+        ) = unbounded();
+        let (sender, output): (
+            Sender<SignedPoLMessage>,
+            Receiver<SignedPoLMessage>,
+        ) = unbounded();
+        self.connection = Some(Connection::Blocking(Box::new(
+            ChannelTransport::new(sender, listener),
+        )));
         (input, output)
     }
 
-    fn abort(&self, reason: &str) {
-        match self.user_output_sender.as_ref() {
-            Some(sender) => {
-                match sender.send(
-                    PoLMessage::Error {
-                        reason: String::from(reason),
-                    },
-                ) {
-                    Ok(result) => debug!("{:?}", result),
-                    Err(_err) => warn!("Couldn't send PoL abort message back to user, check implementation!")
+    /// Talks to the other peer through async message streams, the messages
+    /// of the peer arriving on incoming. Unlike a Transport, the streams
+    /// don't block the executor running start_async.
+    pub fn with_streams<S, K>(mut self, incoming: S, outgoing: K) -> Self
+    where
+        S: Stream<Item = SignedPoLMessage> + Send + 'static,
+        K: Sink<SignedPoLMessage> + Send + 'static,
+    {
+        self.connection = Some(Connection::streams(incoming, outgoing));
+        self
+    }
+
+    /// Uses in-memory message streams, returning the channels for relaying
+    /// the messages of the other peer
+    pub fn open_async_io(
+        &mut self,
+    ) -> (
+        mpsc::UnboundedSender<SignedPoLMessage>,
+        mpsc::UnboundedReceiver<SignedPoLMessage>,
+    ) {
+        let (input, listener) = mpsc::unbounded();
+        let (sender, output) = mpsc::unbounded();
+        self.connection = Some(Connection::streams(listener, sender));
+        (input, output)
+    }
+
+    /// Signs and sends a message to the other peer
+    async fn send(&mut self, message: PoLMessage) -> Result<(), PoLError> {
+        let signed =
+            SignedPoLMessage::new(message, self.identity.as_ref().unwrap());
+        self.connection.as_mut().unwrap().send(signed).await
+    }
+
+    /// Receives a message from the other peer within the timeout, checking
+    /// that it is signed by the same peer as all the messages before it. An
+    /// abort of the peer becomes PoLError::PeerAborted with its error.
+    async fn receive_message(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<PoLMessage, PoLError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let signed = self.connection.as_mut().unwrap().recv(deadline).await?;
+        let signer = signed.verify()?;
+        match self.peer {
+            Some(peer) if peer != signer => Err(PoLError::InvalidSignature),
+            _ => {
+                self.peer = Some(signer);
+                match signed.message {
+                    PoLMessage::Abort { error } => {
+                        Err(PoLError::aborted_by_peer(error))
+                    }
+                    message => Ok(message),
                 }
-            },
-            None => error!("This shouldn't happen, the state machine implementation has a bug.")
+            }
+        }
+    }
+
+    /// Stops our VDF and tells the other peer why the Proof of Latency
+    /// failed, returning the error. Aborts of the peer are not answered.
+    async fn abort(&mut self, error: PoLError) -> PoLError {
+        warn!("Aborting the Proof of Latency: {}", error);
+        self.vdf_cancellation.cancel();
+        if let PoLError::PeerAborted { .. } = error {
+            return error;
+        }
+        let message = PoLMessage::Abort {
+            error: error.clone(),
+        };
+        if self.send(message).await.is_err() {
+            warn!("Couldn't send PoL abort message back to user, check implementation!")
         }
+        error
+    }
+
+    fn combine_generator_parts(&self, our: &Int, other: &Int) -> G::Element {
+        combine_generator_parts(self.group.as_ref().unwrap(), our, other)
     }
 
-    fn combine_generator_parts(&self, our: &Int, other: &Int) -> Int {
-        let mul_str: String = (our * other).to_str_radix(16, true);
-        vdf::util::hash_to_mod(&mul_str, &self.modulus.as_ref().unwrap())
+    /// The unsigned Proof of Latency of this session. ours and theirs are the
+    /// generator parts and caps created by us and the peer.
+    fn unsigned_latency_proof(
+        &self,
+        role: &PoLRole,
+        ours: (&Int, &Int),
+        theirs: (&Int, &Int),
+    ) -> Result<LatencyProof, PoLError> {
+        let our_key = self.identity.as_ref().unwrap().public_key().to_bytes();
+        let peer_key = self.peer.ok_or(PoLError::InvalidSignature)?.to_bytes();
+        let (prover_part, verifier_part) = match role {
+            PoLRole::Prover => (ours, theirs),
+            PoLRole::Verifier => (theirs, ours),
+        };
+        let (prover_key, verifier_key) = match role {
+            PoLRole::Prover => (our_key, peer_key),
+            PoLRole::Verifier => (peer_key, our_key),
+        };
+        let deserialize = |proof: &Option<VDFProof<G>>| {
+            proof
+                .as_ref()
+                .map(|proof| proof.deserialize())
+                .ok_or(PoLError::InvalidProof)
+        };
+        Ok(LatencyProof {
+            version: latency::PROTOCOL_VERSION,
+            prover: deserialize(&self.prover_result)?,
+            verifier: deserialize(&self.verifier_result)?,
+            prover_generator_part: prover_part.0.to_string(),
+            prover_cap: prover_part.1.to_string(),
+            verifier_generator_part: verifier_part.0.to_string(),
+            verifier_cap: verifier_part.1.to_string(),
+            prover_key: prover_key.to_vec(),
+            verifier_key: verifier_key.to_vec(),
+            calibration: match role {
+                PoLRole::Prover => None,
+                PoLRole::Verifier => self.calibration.clone(),
+            },
+            prover_signature: Vec::new(),
+            verifier_signature: Vec::new(),
+        })
     }
 
-    pub fn start(mut self, role: PoLRole) -> Result<bool, PoLStartError> {
-        // Check if user IO is opened
-        if self.user_input_listener.is_none() {
+    /// Checks a Proof of Latency received from the other peer: it must be
+    /// the one of this session, with valid bindings and a valid signature of
+    /// the peer
+    fn check_proof_of_latency(
+        &self,
+        role: &PoLRole,
+        message: PoLMessage,
+        ours: (&Int, &Int),
+        theirs: (&Int, &Int),
+    ) -> Result<LatencyProof, PoLError> {
+        let proof = match message {
+            PoLMessage::ProofOfLatency { proof } => proof,
+            message => {
+                return Err(PoLError::ProtocolViolation {
+                    expected: MessageKind::ProofOfLatency,
+                    received: message.kind(),
+                })
+            }
+        };
+        let mut expected = self.unsigned_latency_proof(role, ours, theirs)?;
+        if *role == PoLRole::Prover {
+            // Only the verifier knows its calibration
+            expected.calibration = proof.calibration.clone();
+        }
+        if proof.digest() != expected.digest() {
+            return Err(PoLError::InvalidProof);
+        }
+        let latency = proof.verify_bindings(self.group.as_ref().unwrap())?;
+        // The verifier signs first, the prover's signature completing the proof
+        proof.verify_signature(&PoLRole::Verifier)?;
+        if *role == PoLRole::Verifier {
+            proof.verify_signature(&PoLRole::Prover)?;
+        }
+        match proof.time_bound(latency, Confidence::NinetyFive) {
+            Some(bound) => info!(
+                "Proof of Latency is correct, latency was {} iterations, {:.1} ms ({:.1}-{:.1} ms)",
+                latency, bound.estimate_ms, bound.lower_ms, bound.upper_ms
+            ),
+            None => info!(
+                "Proof of Latency is correct, latency was {} iterations",
+                latency
+            ),
+        }
+        Ok(proof)
+    }
+
+    /// Checks that the parameters and the connection are set
+    fn prepare(&mut self) -> Result<(), PoLStartError> {
+        if self.connection.is_none()
+            || self.group.is_none()
+            || self.upper_bound.is_none()
+        {
             return Err(PoLStartError);
         }
+        if self.identity.is_none() {
+            debug!("No identity given, generating one for this session");
+            self.identity = Some(Identity::generate());
+        }
+        Ok(())
+    }
+
+    /// Runs the Proof of Latency in a thread of its own, which ends with the
+    /// finished Proof of Latency or the reason it failed
+    pub fn start(
+        mut self,
+        role: PoLRole,
+    ) -> Result<JoinHandle<Result<LatencyProof, PoLError>>, PoLStartError> {
+        self.prepare()?;
+        Ok(thread::spawn(move || {
+            async_std::task::block_on(self.run(role))
+        }))
+    }
+
+    /// Runs the Proof of Latency in the returned future, resolving to the
+    /// finished Proof of Latency. Connections should be opened with
+    /// with_streams or open_async_io, as a Transport blocks the executor.
+    pub fn start_async(
+        mut self,
+        role: PoLRole,
+    ) -> Result<
+        impl Future<Output = Result<LatencyProof, PoLError>> + Send,
+        PoLStartError,
+    > {
+        self.prepare()?;
+        Ok(self.run(role))
+    }
+
+    async fn run(mut self, role: PoLRole) -> Result<LatencyProof, PoLError> {
+        let result = self.run_state_machine(role).await;
+        // The verifier's VDF may not have an upper bound
+        self.vdf_cancellation.cancel();
+        self.connection.as_mut().unwrap().close().await;
+        result
+    }
 
+    async fn run_state_machine(
+        &mut self,
+        role: PoLRole,
+    ) -> Result<LatencyProof, PoLError> {
         // Start a new state machine
         let mut sm = match role {
             PoLRole::Prover => Machine::new(Prover).as_enum(),
             PoLRole::Verifier => Machine::new(Verifier).as_enum(),
         };
 
-        thread::spawn(move || {
-            // Unwrap the user I/O
-            let user_input: &Receiver<PoLMessage> =
-                self.user_input_listener.as_ref().unwrap();
-            let user_output: &Sender<PoLMessage> =
-                self.user_output_sender.as_ref().unwrap();
-
-            // Create the sendable cap and generator part
-            let mut sendable_cap = Int::zero();
-            let mut our_generator_part = Int::zero();
-            let bit_depth = 128;
-
-            loop {
-                sm = match sm {
-                    // PROVER: Create g1 + l1
-                    Variant::InitialProver(m) => {
-                        sendable_cap = Generator::new_safe_prime(bit_depth);
-                        our_generator_part = Generator::new_uint(bit_depth);
-                        m.transition(CreateGeneratorPartAndCap).as_enum()
-                    }
-                    // VERIFIER: Create g2 + l2
-                    Variant::InitialVerifier(m) => {
-                        sendable_cap = Generator::new_safe_prime(bit_depth);
-                        our_generator_part = Generator::new_uint(bit_depth);
-                        m.transition(CreateGeneratorPartAndCap).as_enum()
-                    }
-                    // PROVER: Send g1
-                    Variant::SendingByCreateGeneratorPartAndCap(m) => {
-                        match user_output.send(PoLMessage::GeneratorPart {
+        // Create the sendable cap and generator part
+        let mut sendable_cap = Int::zero();
+        let mut our_generator_part = Int::zero();
+        let mut their_cap = Int::zero();
+        let mut their_generator_part = Int::zero();
+        let bit_depth = 128;
+
+        loop {
+            sm = match sm {
+                // PROVER: Create g1 + l1
+                Variant::InitialProver(m) => {
+                    sendable_cap = Generator::new_safe_prime(bit_depth);
+                    our_generator_part = Generator::new_uint(bit_depth);
+                    m.transition(CreateGeneratorPartAndCap).as_enum()
+                }
+                // VERIFIER: Create g2 + l2
+                Variant::InitialVerifier(m) => {
+                    sendable_cap = Generator::new_safe_prime(bit_depth);
+                    our_generator_part = Generator::new_uint(bit_depth);
+                    m.transition(CreateGeneratorPartAndCap).as_enum()
+                }
+                // PROVER: Send g1
+                Variant::SendingByCreateGeneratorPartAndCap(m) => {
+                    match self
+                        .send(PoLMessage::GeneratorPart {
                             num: our_generator_part.to_string(),
-                        }) {
-                            Ok(_) => m.transition(SendGeneratorPart).as_enum(),
-                            Err(_) => break,
-                        }
+                        })
+                        .await
+                    {
+                        Ok(_) => m.transition(SendGeneratorPart).as_enum(),
+                        Err(err) => return Err(err),
                     }
-                    // VERIFIER: Receive g1, Start VDF, Send g2 + l2
-                    Variant::WaitingByCreateGeneratorPartAndCap(m) => {
-                        let verif_vdf: VDF;
-                        // Receive g1, construct hash(g1+g2)
-                        if let Ok(message) = user_input.recv() {
-                            match message {
-                                PoLMessage::GeneratorPart { num } => {
-                                    // Construct the VDF
-                                    verif_vdf = VDF::new(
-                                        self.modulus.clone().unwrap(),
+                }
+                // VERIFIER: Receive g1, Start VDF, Send g2 + l2
+                Variant::WaitingByCreateGeneratorPartAndCap(m) => {
+                    // Receive g1, construct hash(g1+g2)
+                    let verif_vdf: VDF<G> = match self
+                        .receive_message(self.timeouts.generator_part)
+                        .await
+                    {
+                        Ok(PoLMessage::GeneratorPart { num }) => {
+                            match error::parse_generator_part(&num) {
+                                Ok(their_part) => {
+                                    their_generator_part = their_part;
+                                    VDF::new(
+                                        self.group.clone().unwrap(),
                                         self.combine_generator_parts(
                                             &our_generator_part,
-                                            &Int::from_str_radix(&num, 10)
-                                                .unwrap(),
+                                            &their_generator_part,
                                         ),
                                         self.upper_bound.unwrap(),
                                         vdf::proof::ProofType::Sequential,
-                                    );
+                                    )
                                 }
-                                _ => {
-                                    self.abort("WaitingByCreateGeneratorPart: Expected PoLMessage::GeneratorPart, received something else");
-                                    break;
+                                Err(err) => {
+                                    return Err(self.abort(err).await);
                                 }
                             }
-                        } else {
-                            self.abort("WaitingByCreateGeneratorPart: Could not receive input");
-                            break;
                         }
+                        Ok(message) => {
+                            return Err(self
+                                .abort(PoLError::ProtocolViolation {
+                                    expected: MessageKind::GeneratorPart,
+                                    received: message.kind(),
+                                })
+                                .await);
+                        }
+                        Err(err) => {
+                            return Err(self.abort(err).await);
+                        }
+                    };
 
-                        // Start VDF
-                        let (capper, receiver) = verif_vdf.run_vdf_worker();
-                        self.vdf_capper = Some(capper);
-                        self.vdf_result_channel = Some(receiver);
-
-                        // Send g2 + l2
-                        match user_output.send(
-                            PoLMessage::GeneratorPartAndCap {
-                                generator_part: our_generator_part.to_string(),
-                                cap: sendable_cap.to_string(),
-                            },
-                        ) {
-                            Ok(_) => {
-                                m.transition(SendGeneratorPartAndCap).as_enum()
-                            }
-                            Err(_) => break,
+                    // Start VDF
+                    let (capper, receiver) = verif_vdf
+                        .with_cancellation(self.vdf_cancellation.clone())
+                        .run_async_vdf_worker();
+                    self.vdf_capper = Some(capper);
+                    self.vdf_result_channel = Some(receiver);
+
+                    // Send g2 + l2
+                    match self
+                        .send(PoLMessage::GeneratorPartAndCap {
+                            generator_part: our_generator_part.to_string(),
+                            cap: sendable_cap.to_string(),
+                        })
+                        .await
+                    {
+                        Ok(_) => {
+                            m.transition(SendGeneratorPartAndCap).as_enum()
                         }
+                        Err(err) => return Err(err),
                     }
-                    // PROVER: Receive g2 and l2, Start VDF
-                    Variant::WaitingBySendGeneratorPart(m) => {
-                        let prover_vdf: VDF;
-
-                        if let Ok(message) = user_input.recv() {
-                            match message {
-                                PoLMessage::GeneratorPartAndCap {
-                                    generator_part,
-                                    cap,
-                                } => {
-                                    // Construct the VDF
-                                    prover_vdf = VDF::new(
-                                        self.modulus.clone().unwrap(),
+                }
+                // PROVER: Receive g2 and l2, Start VDF
+                Variant::WaitingBySendGeneratorPart(m) => {
+                    let prover_vdf: VDF<G> = match self
+                        .receive_message(self.timeouts.generator_part_and_cap)
+                        .await
+                    {
+                        Ok(PoLMessage::GeneratorPartAndCap {
+                            generator_part,
+                            cap,
+                        }) => {
+                            let parsed =
+                                error::parse_generator_part(&generator_part)
+                                    .and_then(|part| {
+                                        Ok((part, error::parse_cap(&cap)?))
+                                    });
+                            match parsed {
+                                Ok((their_part, cap)) => {
+                                    their_generator_part = their_part;
+                                    their_cap = cap;
+                                    VDF::new(
+                                        self.group.clone().unwrap(),
                                         self.combine_generator_parts(
                                             &our_generator_part,
-                                            &Int::from_str_radix(
-                                                &generator_part,
-                                                10,
-                                            )
-                                            .unwrap(),
+                                            &their_generator_part,
                                         ),
                                         self.upper_bound.unwrap(),
                                         vdf::proof::ProofType::Parallel,
                                     )
-                                    .with_cap(
-                                        Int::from_str_radix(&cap, 10).unwrap(),
-                                    );
-                                    debug!("{:?}", prover_vdf);
+                                    .with_cancellation(
+                                        self.vdf_cancellation.clone(),
+                                    )
+                                    .with_cap(their_cap.clone())
                                 }
-                                _ => {
-                                    self.abort("WaitingBySendGeneratorPart: Expected PoLMessage::GeneratorPartAndCap, received something else");
-                                    break;
+                                Err(err) => {
+                                    return Err(self.abort(err).await);
                                 }
                             }
-                        } else {
-                            self.abort("WaitingBySendGeneratorPart: Could not receive input");
-                            break;
                         }
+                        Ok(message) => {
+                            return Err(self
+                                .abort(PoLError::ProtocolViolation {
+                                    expected: MessageKind::GeneratorPartAndCap,
+                                    received: message.kind(),
+                                })
+                                .await);
+                        }
+                        Err(err) => {
+                            return Err(self.abort(err).await);
+                        }
+                    };
+                    debug!("{:?}", prover_vdf);
 
-                        let (_, receiver) = prover_vdf.run_vdf_worker();
-                        self.vdf_result_channel = Some(receiver);
+                    let (_, receiver) = prover_vdf.run_async_vdf_worker();
+                    self.vdf_result_channel = Some(receiver);
 
-                        // Transition the state machine
-                        m.transition(ReceiveGeneratorPartAndCap).as_enum()
-                    }
-                    // PROVER: Wait for the VDF to finish and generate a proof
-                    // with the gap given by the verifier, send verifier the
-                    // VDFProof and the cap generated at start.
-                    Variant::EvaluatingByReceiveGeneratorPartAndCap(m) => {
-                        if let Ok(proof) =
-                            self.vdf_result_channel.as_ref().unwrap().recv()
-                        {
-                            match user_output.send(PoLMessage::VDFProofAndCap {
-                                proof: proof.unwrap().deserialize(),
-                                cap: sendable_cap.to_string(),
-                            }) {
-                                Ok(_) => {
-                                    m.transition(EndProverEvaluation).as_enum()
-                                }
-                                Err(_) => break,
-                            }
-                        } else {
-                            self.abort("EvaluatingByReceiveGeneratorPartAndCap: Error received from VDF, check negotiated VDF parameters like the received cap, modulus and the generator.");
-                            break;
+                    // Transition the state machine
+                    m.transition(ReceiveGeneratorPartAndCap).as_enum()
+                }
+                // PROVER: Wait for the VDF to finish and generate a proof
+                // with the gap given by the verifier, send verifier the
+                // VDFProof and the cap generated at start.
+                Variant::EvaluatingByReceiveGeneratorPartAndCap(m) => {
+                    let evaluated = within(
+                        self.timeouts.evaluation,
+                        self.vdf_result_channel.as_mut().unwrap().next(),
+                    )
+                    .await;
+                    let proof = match evaluated {
+                        Ok(Some(Ok(proof))) => proof,
+                        Ok(Some(Err(err))) | Err(err) => {
+                            return Err(self.abort(err).await);
+                        }
+                        Ok(None) => {
+                            return Err(self
+                                .abort(PoLError::ChannelClosed)
+                                .await);
                         }
+                    };
+                    let message = PoLMessage::VDFProofAndCap {
+                        proof: proof.deserialize(),
+                        cap: sendable_cap.to_string(),
+                    };
+                    self.prover_result = Some(proof);
+                    match self.send(message).await {
+                        Ok(_) => m.transition(EndProverEvaluation).as_enum(),
+                        Err(err) => return Err(err),
                     }
-                    // VERIFIER: Receive VDFProof + l1, construct Proof of
-                    // Latency and send it to the user to sign and send to
-                    // Prover
-                    Variant::EvaluatingAndWaitingBySendGeneratorPartAndCap(
-                        m,
-                    ) => {
-                        if let Ok(message) = user_input.recv() {
-                            match message {
-                                PoLMessage::VDFProofAndCap { proof, cap } => {
-                                    // Stop our VDF with cap l1
-                                    match self.receive(
-                                        proof.serialize(),
-                                        Int::from_str_radix(&cap, 10).unwrap(),
-                                    ) {
-                                        (
-                                            Some(our_proof),
-                                            Some(their_proof),
-                                        ) => {
-                                            self.verifier_result =
-                                                Some(our_proof);
-                                            self.prover_result =
-                                                Some(their_proof);
-                                        }
-                                        _ => {
-                                            self.abort("EvaluatingAndWaitingBySendGeneratorPartAndCap: either the verifier or prover VDF proof was incorrect!");
-                                            break;
-                                        }
-                                    }
+                }
+                // VERIFIER: Receive VDFProof + l1, construct Proof of
+                // Latency, sign it and send it to Prover to sign
+                Variant::EvaluatingAndWaitingBySendGeneratorPartAndCap(m) => {
+                    match self
+                        .receive_message(self.timeouts.vdf_proof_and_cap)
+                        .await
+                    {
+                        Ok(PoLMessage::VDFProofAndCap { proof, cap }) => {
+                            let parsed =
+                                proof.serialize::<G>().and_then(|proof| {
+                                    Ok((proof, error::parse_cap(&cap)?))
+                                });
+                            let their_proof = match parsed {
+                                Ok((proof, cap)) => {
+                                    their_cap = cap;
+                                    proof
+                                }
+                                Err(err) => {
+                                    return Err(self.abort(err).await);
+                                }
+                            };
+                            // Stop our VDF with cap l1
+                            match self
+                                .receive(their_proof, their_cap.clone())
+                                .await
+                            {
+                                (Some(our_proof), Some(their_proof)) => {
+                                    self.verifier_result = Some(our_proof);
+                                    self.prover_result = Some(their_proof);
                                 }
                                 _ => {
-                                    self.abort("EvaluatingAndWaitingBySendGeneratorPartAndCap: Expected PoLMessage::VDFProofAndCap, received something else");
-                                    break;
+                                    return Err(self
+                                        .abort(PoLError::InvalidProof)
+                                        .await);
                                 }
                             }
-                        } else {
-                            self.abort("EvaluatingAndWaitingBySendGeneratorPartAndCap: Could not receive input");
-                            break;
                         }
+                        Ok(message) => {
+                            return Err(self
+                                .abort(PoLError::ProtocolViolation {
+                                    expected: MessageKind::VDFProofAndCap,
+                                    received: message.kind(),
+                                })
+                                .await);
+                        }
+                        Err(err) => {
+                            return Err(self.abort(err).await);
+                        }
+                    }
 
-                        match user_output.send(PoLMessage::ProofOfLatency {
-                            verifier: self
-                                .verifier_result
-                                .as_ref()
-                                .unwrap()
-                                .deserialize(),
-                            prover: self
-                                .prover_result
-                                .as_ref()
-                                .unwrap()
-                                .deserialize(),
-                        }) {
-                            Ok(_) => {
-                                m.transition(EndVerifierEvaluation).as_enum()
-                            }
-                            Err(_) => break,
+                    // The prover's proof is only signed if it was evaluated in
+                    // our group, from the generator and with the cap of this
+                    // session
+                    let unsigned = self
+                        .unsigned_latency_proof(
+                            &PoLRole::Verifier,
+                            (&our_generator_part, &sendable_cap),
+                            (&their_generator_part, &their_cap),
+                        )
+                        .and_then(|proof| {
+                            proof.verify_bindings(
+                                self.group.as_ref().unwrap(),
+                            )?;
+                            Ok(proof)
+                        });
+                    let mut proof = match unsigned {
+                        Ok(proof) => proof,
+                        Err(err) => {
+                            return Err(self.abort(err).await);
                         }
+                    };
+                    proof.sign(
+                        self.identity.as_ref().unwrap(),
+                        &PoLRole::Verifier,
+                    );
+                    self.latency_proof = Some(proof.clone());
+                    match self.send(PoLMessage::ProofOfLatency { proof }).await
+                    {
+                        Ok(_) => m.transition(EndVerifierEvaluation).as_enum(),
+                        Err(err) => return Err(err),
                     }
-                    // PROVER: Receive Proof of Latency from Verifier, check
-                    // that it is correct and has a signature, and send back to
-                    // Verifier with a signature
-                    Variant::WaitingByEndProverEvaluation(m) => {
-                        if let Ok(message) = user_input.recv() {
-                            match message {
-                                PoLMessage::ProofOfLatency {
-                                    prover,
-                                    verifier,
-                                } => {
-                                    match user_output.send(
-                                        PoLMessage::ProofOfLatency {
-                                            verifier,
-                                            prover,
-                                        },
-                                    ) {
-                                        Ok(_) => m
-                                            .transition(SignVerifierVDF)
-                                            .as_enum(),
-                                        Err(_) => break,
-                                    }
-                                }
-                                _ => {
-                                    self.abort("WaitingByEndProverEvaluation: Expected PoLMessage::ProofOfLatency, received something else");
-                                    break;
-                                }
+                }
+                // PROVER: Receive Proof of Latency from Verifier, check
+                // that it is correct and has a signature, and send back to
+                // Verifier with a signature
+                Variant::WaitingByEndProverEvaluation(m) => {
+                    let checked = self
+                        .receive_message(self.timeouts.proof_of_latency)
+                        .await
+                        .and_then(|message| {
+                            if let PoLMessage::ProofOfLatency { proof } =
+                                &message
+                            {
+                                self.verifier_result =
+                                    proof.verifier.serialize::<G>().ok();
                             }
-                        } else {
-                            self.abort("WaitingByEndProverEvaluation: Could not receive input");
-                            break;
+                            self.check_proof_of_latency(
+                                &PoLRole::Prover,
+                                message,
+                                (&our_generator_part, &sendable_cap),
+                                (&their_generator_part, &their_cap),
+                            )
+                        });
+                    let mut proof = match checked {
+                        Ok(proof) => proof,
+                        Err(err) => {
+                            return Err(self.abort(err).await);
                         }
+                    };
+                    proof.sign(
+                        self.identity.as_ref().unwrap(),
+                        &PoLRole::Prover,
+                    );
+                    self.latency_proof = Some(proof.clone());
+                    match self.send(PoLMessage::ProofOfLatency { proof }).await
+                    {
+                        Ok(_) => m.transition(SignVerifierVDF).as_enum(),
+                        Err(err) => return Err(err),
                     }
-                    // VERIFIER: Receive a ready Proof of Latency from Prover,
-                    // make it available to the network
-                    Variant::WaitingByEndVerifierEvaluation(m) => {
-                        m.transition(ReceiveProofOfLatency).as_enum()
-                    }
-                    // PROVER: Make proof available to the network
-                    Variant::ProofReadyBySignVerifierVDF(_) => {
-                        break;
-                    }
-                    // VERIFIER: Make proof available to the network
-                    Variant::ProofReadyByReceiveProofOfLatency(_) => {
-                        break;
+                }
+                // VERIFIER: Receive a Proof of Latency signed by both
+                // peers from Prover, make it available to the network
+                Variant::WaitingByEndVerifierEvaluation(m) => {
+                    let checked = self
+                        .receive_message(self.timeouts.proof_of_latency)
+                        .await
+                        .and_then(|message| {
+                            self.check_proof_of_latency(
+                                &PoLRole::Verifier,
+                                message,
+                                (&our_generator_part, &sendable_cap),
+                                (&their_generator_part, &their_cap),
+                            )
+                        });
+                    match checked {
+                        Ok(proof) => {
+                            self.latency_proof = Some(proof);
+                            m.transition(ReceiveProofOfLatency).as_enum()
+                        }
+                        Err(err) => {
+                            return Err(self.abort(err).await);
+                        }
                     }
                 }
+                // PROVER: Make proof available to the network
+                Variant::ProofReadyBySignVerifierVDF(_) => {
+                    return Ok(self.latency_proof.clone().unwrap());
+                }
+                // VERIFIER: Make proof available to the network
+                Variant::ProofReadyByReceiveProofOfLatency(_) => {
+                    return Ok(self.latency_proof.clone().unwrap());
+                }
             }
-        });
-
-        Ok(true)
+        }
     }
 
-    pub fn receive(
-        &self,
-        their_proof: VDFProof,
+    pub async fn receive(
+        &mut self,
+        their_proof: VDFProof<G>,
         cap: Int,
-    ) -> (Option<VDFProof>, Option<VDFProof>) {
+    ) -> (Option<VDFProof<G>>, Option<VDFProof<G>>) {
         // Send received signature from the other peer, "capping off" the VDF
         if self.vdf_capper.as_ref().unwrap().send(cap).is_err() {
             debug!(
@@ -497,15 +994,16 @@ impl ProofOfLatency {
         };
 
         // Wait for response from VDF worker
-        if let Ok(Ok(proof)) = self.vdf_result_channel.as_ref().unwrap().recv()
+        if let Some(Ok(proof)) =
+            self.vdf_result_channel.as_mut().unwrap().next().await
         {
             debug!(
                 "VDF ran for {:?} times!\nThe output being {:?}",
                 proof.output.iterations, proof.output.result
             );
 
-            let iter_prover: u32 = proof.output.iterations;
-            let iter_verifier: u32 = their_proof.output.iterations;
+            let iter_prover: u64 = proof.output.iterations;
+            let iter_verifier: u64 = their_proof.output.iterations;
             let difference: Int = if iter_prover > iter_verifier {
                 Int::from(iter_prover - iter_verifier)
             } else {
@@ -517,6 +1015,16 @@ impl ProofOfLatency {
                         "Both proofs are correct! Latency between peers was {:?} iterations.",
                         difference
                     );
+                if let Some(calibration) = self.calibration.as_ref() {
+                    let bound = calibration.time_bound(
+                        proof.abs_difference(&their_proof),
+                        Confidence::NinetyFive,
+                    );
+                    info!(
+                        "That is {:.1} ms, between {:.1} and {:.1} ms with 95% confidence",
+                        bound.estimate_ms, bound.lower_ms, bound.upper_ms
+                    );
+                }
 
                 return (Some(proof), Some(their_proof));
             }
@@ -525,16 +1033,35 @@ impl ProofOfLatency {
     }
 }
 
+/// Waits for future until the timeout passes
+async fn within<F: Future>(
+    timeout: Option<Duration>,
+    future: F,
+) -> Result<F::Output, PoLError> {
+    match timeout {
+        Some(timeout) => async_std::future::timeout(timeout, future)
+            .await
+            .map_err(|_| PoLError::Timeout),
+        None => Ok(future.await),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ramp_primes::Verification;
+    use crate::vdf::bigint::Verification;
     use std::str::FromStr;
+    use std::time::Duration;
+
+    fn rsa_group() -> RsaGroup {
+        RsaGroup::new(Int::from_str(RSA_2048).unwrap())
+    }
 
     #[test]
     fn runs_without_blocking() {
         let modulus = Int::from_str(RSA_2048).unwrap();
-        let mut pol = ProofOfLatency::default().init(modulus, u32::MAX);
+        let mut pol =
+            ProofOfLatency::default().init(RsaGroup::new(modulus), u64::MAX);
 
         let (_input, _output) = pol.open_io();
 
@@ -546,23 +1073,28 @@ mod tests {
         let modulus = Int::from_str(RSA_2048).unwrap();
         let rand1 = Generator::new_uint(128);
         let rand2 = Generator::new_uint(128);
-        let pol = ProofOfLatency::default().init(modulus, u32::MAX);
-        let result1 = pol.combine_generator_parts(&rand1, &rand2);
-        let result2 = pol.combine_generator_parts(&rand2, &rand1);
+        let pol =
+            ProofOfLatency::default().init(RsaGroup::new(modulus), u64::MAX);
+        let group = pol.group.as_ref().unwrap();
+        let result1 = combine_generator_parts(group, &rand1, &rand2);
+        let result2 = combine_generator_parts(group, &rand2, &rand1);
         assert_eq!(result1, result2);
     }
 
     #[test]
     fn runs_prover_state_machine_in_correct_order() {
         let modulus = Int::from_str(RSA_2048).unwrap();
-        let mut pol = ProofOfLatency::default().init(modulus, 42);
+        let mut pol =
+            ProofOfLatency::default().init(RsaGroup::new(modulus), 42);
         let (input, output) = pol.open_io();
+        let identity = Identity::generate();
 
         assert!(pol.start(PoLRole::Prover).is_ok());
 
         // First, we should receive a generator part
-        if let Ok(message) = output.recv() {
-            match message {
+        if let Ok(signed) = output.recv() {
+            assert!(signed.verify().is_ok());
+            match signed.message {
                 PoLMessage::GeneratorPart { num } => {
                     assert!(&Int::from_str_radix(&num, 10).is_ok())
                 }
@@ -577,17 +1109,20 @@ mod tests {
         let cap = Generator::new_prime(64);
         let generator_part = Generator::new_uint(64);
         assert!(input
-            .send(PoLMessage::GeneratorPartAndCap {
-                generator_part: generator_part.to_str_radix(10, false),
-                cap: cap.to_str_radix(10, false)
-            })
+            .send(SignedPoLMessage::new(
+                PoLMessage::GeneratorPartAndCap {
+                    generator_part: generator_part.to_str_radix(10, false),
+                    cap: cap.to_str_radix(10, false)
+                },
+                &identity
+            ))
             .is_ok());
 
         // Next up, we should receive a VDF proof with another cap
-        if let Ok(message) = output.recv() {
-            match message {
+        if let Ok(signed) = output.recv() {
+            match signed.message {
                 PoLMessage::VDFProofAndCap { proof, cap } => {
-                    assert!(proof.serialize().verify());
+                    assert!(proof.serialize::<RsaGroup>().unwrap().verify());
                     assert!(Verification::verify_prime(
                         Int::from_str_radix(&cap, 10).unwrap()
                     ));
@@ -598,4 +1133,455 @@ mod tests {
             panic!()
         }
     }
+
+    #[test]
+    fn state_machine_drives_the_transport() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let pol = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus), 42)
+            .with_transport(transport::TcpTransport::connect(address).unwrap());
+        let mut peer = transport::TcpTransport::accept(&listener).unwrap();
+
+        let session = pol.start(PoLRole::Prover).unwrap();
+        assert!(peer.peer_address().is_some());
+
+        // The prover starts by sending its generator part, and aborts when
+        // the peer answers with something else than its own part and cap
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        let signed = peer.recv(Some(deadline)).unwrap();
+        assert!(matches!(signed.message, PoLMessage::GeneratorPart { .. }));
+        assert!(peer
+            .send(SignedPoLMessage::new(
+                PoLMessage::Cap {
+                    num: String::from("7")
+                },
+                &Identity::generate()
+            ))
+            .is_ok());
+        let error = PoLError::ProtocolViolation {
+            expected: MessageKind::GeneratorPartAndCap,
+            received: MessageKind::Cap,
+        };
+        assert_eq!(
+            peer.recv(Some(deadline)).unwrap().message,
+            PoLMessage::Abort {
+                error: error.clone()
+            }
+        );
+        assert_eq!(peer.recv(Some(deadline)), Err(PoLError::ChannelClosed));
+        assert_eq!(session.join().unwrap(), Err(error));
+    }
+
+    #[test]
+    fn malformed_generator_part_is_reported() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let mut pol =
+            ProofOfLatency::default().init(RsaGroup::new(modulus), 42);
+        let (input, output) = pol.open_io();
+
+        let session = pol.start(PoLRole::Verifier).unwrap();
+        assert!(input
+            .send(SignedPoLMessage::new(
+                PoLMessage::GeneratorPart {
+                    num: String::from("not a number")
+                },
+                &Identity::generate()
+            ))
+            .is_ok());
+
+        match output.recv().map(|signed| signed.message) {
+            Ok(PoLMessage::Abort {
+                error: PoLError::Parse { input },
+            }) => assert_eq!(input, "not a number"),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        assert!(matches!(
+            session.join().unwrap(),
+            Err(PoLError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn zero_generator_part_is_rejected() {
+        let mut pol = ProofOfLatency::default().init(rsa_group(), 42);
+        let (input, output) = pol.open_io();
+
+        let session = pol.start(PoLRole::Verifier).unwrap();
+        assert!(input
+            .send(SignedPoLMessage::new(
+                PoLMessage::GeneratorPart {
+                    num: String::from("0")
+                },
+                &Identity::generate()
+            ))
+            .is_ok());
+
+        assert_eq!(
+            output.recv().unwrap().message,
+            PoLMessage::Abort {
+                error: PoLError::InvalidGeneratorPart
+            }
+        );
+        assert_eq!(
+            session.join().unwrap(),
+            Err(PoLError::InvalidGeneratorPart)
+        );
+    }
+
+    #[test]
+    fn verifier_refuses_to_sign_a_proof_in_another_group() {
+        let mut pol = ProofOfLatency::default().init(rsa_group(), u64::MAX);
+        let (input, output) = pol.open_async_io();
+        let session = pol.start_async(PoLRole::Verifier).unwrap();
+        let peer = Identity::generate();
+        let send = |message| {
+            assert!(input
+                .unbounded_send(SignedPoLMessage::new(message, &peer))
+                .is_ok())
+        };
+        send(PoLMessage::GeneratorPart {
+            num: String::from("12"),
+        });
+
+        // A proof over a small modulus with a cap of the prover's choosing,
+        // correct on its own but calculated without any work
+        let toy = RsaGroup::new(Int::from(3233));
+        let generator = Int::from(5);
+        let output_value =
+            (0..10).fold(generator.clone(), |x, _| toy.square(&x));
+        let result = vdf::evaluation::VDFResult {
+            result: output_value,
+            iterations: 10,
+        };
+        let proof = VDFProof::new(
+            &toy,
+            &generator,
+            &result,
+            &Int::from(1_000_003),
+            &vdf::proof::ProofType::Sequential,
+        )
+        .calculate()
+        .unwrap();
+        assert!(proof.verify());
+        send(PoLMessage::VDFProofAndCap {
+            proof: proof.deserialize(),
+            cap: Generator::new_safe_prime(128).to_string(),
+        });
+
+        assert_eq!(
+            async_std::task::block_on(session),
+            Err(PoLError::InvalidGroup)
+        );
+        let messages: Vec<PoLMessage> = async_std::task::block_on(
+            output.map(|signed| signed.message).collect(),
+        );
+        assert_eq!(messages[0].kind(), MessageKind::GeneratorPartAndCap);
+        assert_eq!(
+            messages[1],
+            PoLMessage::Abort {
+                error: PoLError::InvalidGroup
+            }
+        );
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[test]
+    fn unexpected_message_is_a_protocol_violation() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let mut pol =
+            ProofOfLatency::default().init(RsaGroup::new(modulus), 42);
+        let (input, output) = pol.open_io();
+
+        assert!(pol.start(PoLRole::Verifier).is_ok());
+        assert!(input
+            .send(SignedPoLMessage::new(
+                PoLMessage::Cap {
+                    num: String::from("7")
+                },
+                &Identity::generate()
+            ))
+            .is_ok());
+
+        assert_eq!(
+            output.recv().unwrap().message,
+            PoLMessage::Abort {
+                error: PoLError::ProtocolViolation {
+                    expected: MessageKind::GeneratorPart,
+                    received: MessageKind::Cap,
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn start_async_resolves_to_the_error() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let mut pol =
+            ProofOfLatency::default().init(RsaGroup::new(modulus), 42);
+        let (input, mut output) = pol.open_async_io();
+        let session = pol.start_async(PoLRole::Verifier).unwrap();
+        assert!(input
+            .unbounded_send(SignedPoLMessage::new(
+                PoLMessage::Cap {
+                    num: String::from("7")
+                },
+                &Identity::generate()
+            ))
+            .is_ok());
+
+        let error = PoLError::ProtocolViolation {
+            expected: MessageKind::GeneratorPart,
+            received: MessageKind::Cap,
+        };
+        assert_eq!(async_std::task::block_on(session), Err(error.clone()));
+        assert_eq!(
+            async_std::task::block_on(output.next()).map(|s| s.message),
+            Some(PoLMessage::Abort { error })
+        );
+    }
+
+    #[test]
+    fn peer_abort_is_returned_without_a_reply() {
+        let mut pol = ProofOfLatency::default().init(rsa_group(), 42);
+        let (input, output) = pol.open_async_io();
+        let session = pol.start_async(PoLRole::Verifier).unwrap();
+        assert!(input
+            .unbounded_send(SignedPoLMessage::new(
+                PoLMessage::Abort {
+                    error: PoLError::Timeout
+                },
+                &Identity::generate()
+            ))
+            .is_ok());
+
+        assert_eq!(
+            async_std::task::block_on(session),
+            Err(PoLError::aborted_by_peer(PoLError::Timeout))
+        );
+        let messages: Vec<SignedPoLMessage> =
+            async_std::task::block_on(output.collect());
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn states_time_out_by_default() {
+        let timeouts = Timeouts::default();
+        assert_eq!(timeouts.generator_part, Some(DEFAULT_MESSAGE_TIMEOUT));
+        assert_eq!(timeouts.evaluation, Some(DEFAULT_EVALUATION_TIMEOUT));
+        assert_eq!(ProofOfLatency::<RsaGroup>::default().timeouts, timeouts);
+        // Waiting forever has to be asked for
+        assert_eq!(Timeouts::none().vdf_proof_and_cap, None);
+    }
+
+    #[test]
+    fn silent_peer_times_out() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let mut pol = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus), u64::MAX)
+            .with_timeouts(Timeouts {
+                generator_part: Some(Duration::from_secs(5)),
+                vdf_proof_and_cap: Some(Duration::from_millis(50)),
+                ..Timeouts::default()
+            });
+        let (input, output) = pol.open_async_io();
+        let session = pol.start_async(PoLRole::Verifier).unwrap();
+        let peer = Identity::generate();
+        assert!(input
+            .unbounded_send(SignedPoLMessage::new(
+                PoLMessage::GeneratorPart {
+                    num: String::from("12")
+                },
+                &peer
+            ))
+            .is_ok());
+
+        // The verifier's VDF has started, and is stopped by the timeout
+        assert_eq!(async_std::task::block_on(session), Err(PoLError::Timeout));
+        let messages: Vec<PoLMessage> = async_std::task::block_on(
+            output.map(|signed| signed.message).collect(),
+        );
+        assert_eq!(messages[0].kind(), MessageKind::GeneratorPartAndCap);
+        assert_eq!(
+            messages[1],
+            PoLMessage::Abort {
+                error: PoLError::Timeout
+            }
+        );
+    }
+
+    #[test]
+    fn prover_and_verifier_run_as_futures() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let (to_verifier, verifier_incoming) = mpsc::unbounded();
+        let (to_prover, prover_incoming) = mpsc::unbounded();
+        let prover = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus.clone()), 100)
+            .with_streams(prover_incoming, to_verifier)
+            .start_async(PoLRole::Prover)
+            .unwrap();
+        let verifier = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus), u64::MAX)
+            .with_streams(verifier_incoming, to_prover)
+            .start_async(PoLRole::Verifier)
+            .unwrap();
+
+        // Both sessions run on this thread
+        let (prover_proof, verifier_proof) =
+            async_std::task::block_on(futures::future::join(prover, verifier));
+        let proof = prover_proof.unwrap();
+        assert_eq!(verifier_proof, Ok(proof.clone()));
+        assert!(proof.verify(&rsa_group()).is_ok());
+    }
+
+    #[test]
+    fn prover_and_verifier_sign_the_proof_of_latency() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let prover_identity = Identity::generate();
+        let verifier_identity = Identity::generate();
+        let prover_key = prover_identity.public_key();
+        let verifier_key = verifier_identity.public_key();
+
+        let mut prover = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus.clone()), 100)
+            .with_identity(prover_identity)
+            .with_peer(verifier_key);
+        let calibration = Calibration {
+            bit_length: RsaGroup::new(modulus.clone()).bit_length(),
+            sample_ms: 100,
+            samples: vec![90_000, 110_000],
+        };
+
+        // The verifier's VDF has to run until the prover's cap stops it
+        let mut verifier = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus), u64::MAX)
+            .with_calibration(calibration.clone())
+            .with_identity(verifier_identity)
+            .with_peer(prover_key);
+        let (prover_input, prover_output) = prover.open_io();
+        let (verifier_input, verifier_output) = verifier.open_io();
+
+        // Relay the messages between the peers, keeping the prover's last
+        let (last_sender, last_receiver) = unbounded();
+        thread::spawn(move || {
+            while let Ok(signed) = verifier_output.recv() {
+                if prover_input.send(signed).is_err() {
+                    break;
+                }
+            }
+        });
+        thread::spawn(move || {
+            while let Ok(signed) = prover_output.recv() {
+                let _ = last_sender.send(signed.clone());
+                if verifier_input.send(signed).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let verifier = verifier.start(PoLRole::Verifier).unwrap();
+        let prover = prover.start(PoLRole::Prover).unwrap();
+
+        let bundle = last_receiver.iter().last().unwrap();
+        assert_eq!(bundle.verify(), Ok(prover_key));
+        let proof = match bundle.message {
+            PoLMessage::ProofOfLatency { proof } => proof,
+            other => panic!("Expected a Proof of Latency, got {:?}", other),
+        };
+        assert_eq!(proof.prover_key, prover_key.to_bytes().to_vec());
+        assert_eq!(proof.verifier_key, verifier_key.to_bytes().to_vec());
+        let latency = proof.verify(&rsa_group()).unwrap();
+        assert_eq!(
+            latency,
+            proof.verifier.output.iterations - proof.prover.output.iterations
+        );
+        assert_eq!(proof.calibration, Some(calibration));
+        // Both sessions end with the Proof of Latency signed by both
+        assert_eq!(prover.join().unwrap(), Ok(proof.clone()));
+        assert_eq!(verifier.join().unwrap(), Ok(proof.clone()));
+        assert!(proof.time_bound(latency, Confidence::NinetyFive).is_some());
+
+        // Every binding is checked
+        let mut swapped_caps = proof.clone();
+        swapped_caps.prover_cap = proof.verifier_cap.clone();
+        swapped_caps.verifier_cap = proof.prover_cap.clone();
+        assert_eq!(
+            swapped_caps.verify(&rsa_group()),
+            Err(PoLError::InvalidCap)
+        );
+
+        let mut pietrzak = proof.clone();
+        pietrzak.verifier.proof_type = vdf::proof::ProofType::Pietrzak;
+        assert_eq!(pietrzak.verify(&rsa_group()), Err(PoLError::InvalidCap));
+
+        let mut other_generator = proof.clone();
+        other_generator.prover_generator_part = String::from("42");
+        assert_eq!(
+            other_generator.verify(&rsa_group()),
+            Err(PoLError::InvalidProof)
+        );
+
+        let mut faster_calibration = proof.clone();
+        if let Some(calibration) = faster_calibration.calibration.as_mut() {
+            calibration.samples[0] *= 2;
+        }
+        assert_eq!(
+            faster_calibration.verify(&rsa_group()),
+            Err(PoLError::InvalidSignature)
+        );
+
+        let mut zero_part = proof.clone();
+        zero_part.verifier_generator_part = String::from("0");
+        assert_eq!(
+            zero_part.verify(&rsa_group()),
+            Err(PoLError::InvalidGeneratorPart)
+        );
+
+        // Proofs are only valid in the group they are verified in
+        assert_eq!(
+            proof.verify(&RsaGroup::new(Int::from(3233))),
+            Err(PoLError::InvalidGroup)
+        );
+
+        let mut swapped_proofs = proof.clone();
+        swapped_proofs.prover = proof.verifier.clone();
+        swapped_proofs.verifier = proof.prover.clone();
+        assert!(swapped_proofs.verify(&rsa_group()).is_err());
+
+        let mut unsigned = proof;
+        unsigned.prover_signature = Vec::new();
+        assert_eq!(unsigned.verify_bindings(&rsa_group()), Ok(latency));
+        assert_eq!(
+            unsigned.verify(&rsa_group()),
+            Err(PoLError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn messages_from_an_unknown_peer_are_rejected() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let mut pol = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus), 42)
+            .with_peer(Identity::generate().public_key());
+        let (input, output) = pol.open_io();
+
+        let session = pol.start(PoLRole::Verifier).unwrap();
+        assert!(input
+            .send(SignedPoLMessage::new(
+                PoLMessage::GeneratorPart {
+                    num: String::from("42")
+                },
+                &Identity::generate()
+            ))
+            .is_ok());
+
+        assert_eq!(
+            output.recv().unwrap().message,
+            PoLMessage::Abort {
+                error: PoLError::InvalidSignature
+            }
+        );
+        assert_eq!(session.join().unwrap(), Err(PoLError::InvalidSignature));
+    }
 }
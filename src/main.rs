@@ -1,47 +1,177 @@
 #[macro_use]
 extern crate log;
 
+use futures::channel::mpsc;
+use futures::StreamExt;
+use proof_of_latency::identity::{Identity, SignedPoLMessage};
+use proof_of_latency::latency::LatencyProof;
+use proof_of_latency::node::{self, PoLWorker};
+use proof_of_latency::store::ProofStore;
+use proof_of_latency::vdf::bigint::Generator;
+use proof_of_latency::vdf::bigint::Int;
+use proof_of_latency::vdf::group::RsaGroup;
 use proof_of_latency::vdf::util::hash_to_prime;
 use proof_of_latency::{PoLMessage, PoLRole, ProofOfLatency, RSA_2048};
-use ramp::Int;
-use ramp_primes::Generator;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
 use std::time::Instant;
-// use ockam::{Context, Result, Route, SecureChannel, TcpTransport, Vault, TCP};
 
-// #[ockam::node]
+/// Address the Verifier node listens on unless given one
+const NODE_ADDRESS: &str = "127.0.0.1:4000";
+
+/// Directory of the proof stores and identities of the nodes, one per role.
+/// Proofs are stored by the keys of the peers, so a node keeps its identity
+/// across restarts.
+const PROOF_STORE: &str = "proofs";
+
+/// Usage: proof_of_latency [verifier|prover [address]]. Without arguments,
+/// plays both peers of a Proof of Latency in this process.
 fn main() {
     env_logger::init();
 
-    //   // Initialize the TCP Transport.
-    //   let tcp = TcpTransport::create(&ctx).await?;
-    //
-    //   // Create a TCP connection.
-    //   tcp.connect("127.0.0.1:3000").await?;
-    //
-    //   let vault = Vault::create(&ctx).await?;
-    //
-    //   // Connect to a secure channel listener and perform a handshake.
-    //   let channel = SecureChannel::create(
-    //       &mut ctx,
-    //       // route to the secure channel listener
-    //       Route::new()
-    //           .append_t(TCP, "127.0.0.1:4000") // responder node
-    //           .append("secure_channel_listener"), // secure_channel_listener
-    // on responder node,       &vault,
-    //   )
-    //       .await?;
-    //
-    //   // Send a message to the echoer worker via the channel.
-    //   ctx.send(
-    //       Route::new().append(channel.address()).append("echoer"),
-    //       "Hello Ockam!".to_string(),
-    //   )
-    //       .await?;
-    //
-    //   // Wait to receive a reply and print it.
-    //   let reply = ctx.receive::<String>().await?;
-    //   println!("App Received: {}", reply); // should print "Hello Ockam!"
+    let args: Vec<String> = env::args().skip(1).collect();
+    let address = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| String::from(NODE_ADDRESS));
+    match args.first().map(String::as_str) {
+        Some("verifier") => run_verifier(address),
+        Some("prover") => run_prover(address),
+        _ => run_locally(),
+    }
+}
+
+/// Hosts a Verifier behind a secure channel listener until it is stopped
+fn run_verifier(address: String) {
+    let group = RsaGroup::new(Int::from_str_radix(RSA_2048, 10).unwrap());
+    let (store, identity) =
+        match (open_store("verifier"), load_identity("verifier")) {
+            (Some(store), Some(identity)) => (store, identity),
+            _ => return,
+        };
+    let (ctx, mut executor) = ockam::start_node();
+    let result = executor.execute(async move {
+        let (proofs, mut verified) = mpsc::unbounded();
+        let worker = PoLWorker::verifier(group.clone(), identity, proofs);
+        if let Err(err) = node::listen(&ctx, &address, worker).await {
+            error!("Couldn't listen on {}: {:?}", address, err);
+            return;
+        }
+        info!("Verifier listening on {}", address);
+        while let Some(proof) = verified.next().await {
+            report(&store, &group, proof);
+        }
+    });
+    if let Err(err) = result {
+        error!("Verifier node stopped: {:?}", err);
+    }
+}
+
+/// Proves latency to the Verifier node at address, stopping when done
+fn run_prover(address: String) {
+    let group = RsaGroup::new(Int::from_str_radix(RSA_2048, 10).unwrap());
+    let (store, identity) =
+        match (open_store("prover"), load_identity("prover")) {
+            (Some(store), Some(identity)) => (store, identity),
+            _ => return,
+        };
+    let (mut ctx, mut executor) = ockam::start_node();
+    let result = executor.execute(async move {
+        match node::connect(&ctx, &address).await {
+            Ok(verifier) => {
+                let (proofs, mut proved) = mpsc::unbounded();
+                let worker = PoLWorker::prover(
+                    group.clone(),
+                    150000,
+                    identity,
+                    verifier,
+                    proofs,
+                );
+                if ctx.start_worker("prover", worker).await.is_ok() {
+                    if let Some(proof) = proved.next().await {
+                        report(&store, &group, proof);
+                    }
+                }
+            }
+            Err(err) => error!("Couldn't connect to {}: {:?}", address, err),
+        }
+        if ctx.stop().await.is_err() {
+            error!("Couldn't stop the node");
+        }
+    });
+    if let Err(err) = result {
+        error!("Prover node stopped: {:?}", err);
+    }
+}
+
+fn open_store(role: &str) -> Option<ProofStore> {
+    let path = Path::new(PROOF_STORE).join(role);
+    match ProofStore::open(&path) {
+        Ok(store) => Some(store),
+        Err(err) => {
+            error!("Couldn't open the proof store at {:?}: {}", path, err);
+            None
+        }
+    }
+}
 
+/// The identity of the node in role, generated on its first start
+fn load_identity(role: &str) -> Option<Identity> {
+    let path = Path::new(PROOF_STORE).join(format!("{}.key", role));
+    let loaded = match fs::read(&path) {
+        Ok(bytes) => {
+            Identity::from_bytes(&bytes).map_err(|err| err.to_string())
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let identity = Identity::generate();
+            save_identity(&path, &identity)
+                .map(|_| identity)
+                .map_err(|err| err.to_string())
+        }
+        Err(err) => Err(err.to_string()),
+    };
+    match loaded {
+        Ok(identity) => {
+            info!("Node identity {:?}", identity.public_key().to_bytes());
+            Some(identity)
+        }
+        Err(err) => {
+            error!("Couldn't load the identity at {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Writes the secret key of identity to a new file only the user can read
+fn save_identity(path: &Path, identity: &Identity) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(&identity.to_bytes())
+}
+
+/// Stores the proof if it verifies
+fn report(store: &ProofStore, group: &RsaGroup, proof: LatencyProof) {
+    match store.insert(group, proof) {
+        Ok(stored) => info!(
+            "Proof of Latency between {:?} and {:?}: {} iterations",
+            stored.proof.prover_key, stored.proof.verifier_key, stored.latency
+        ),
+        Err(err) => error!("{}", err),
+    }
+}
+
+/// The demo of a Prover, the other peer played by this function
+fn run_locally() {
     let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
 
     let timer = Instant::now();
@@ -55,17 +185,27 @@ fn main() {
     let diff = &prime1 - &modulus;
     debug!("The prime is {:?} larger than the modulus!", diff);
 
-    let mut pol = ProofOfLatency::default().init(modulus, 150000);
+    let mut pol =
+        ProofOfLatency::default().init(RsaGroup::new(modulus), 150000);
     let (input, output) = pol.open_io();
     debug!("Proof of latency instance created");
 
-    match pol.start(PoLRole::Prover) {
-        Ok(_) => info!("PoL state machine started"),
-        Err(_) => error!("Couldn't start the PoL state machine"),
-    }
+    let session = match pol.start(PoLRole::Prover) {
+        Ok(session) => {
+            info!("PoL state machine started");
+            session
+        }
+        Err(_) => {
+            error!("Couldn't start the PoL state machine");
+            return;
+        }
+    };
 
-    if let Ok(message) = output.recv() {
-        match message {
+    // The other peer, played by this function
+    let identity = Identity::generate();
+
+    if let Ok(signed) = output.recv() {
+        match signed.message {
             PoLMessage::GeneratorPart { num } => {
                 info!("Generator part received: {:?}", num)
             }
@@ -77,18 +217,21 @@ fn main() {
 
     let cap = Generator::new_safe_prime(128);
     let generator_part = Generator::new_uint(128);
-    match input.send(PoLMessage::GeneratorPartAndCap {
-        generator_part: generator_part.to_str_radix(10, false),
-        cap: cap.to_str_radix(10, false),
-    }) {
+    match input.send(SignedPoLMessage::new(
+        PoLMessage::GeneratorPartAndCap {
+            generator_part: generator_part.to_str_radix(10, false),
+            cap: cap.to_str_radix(10, false),
+        },
+        &identity,
+    )) {
         Ok(_) => info!("Received g2, l2"),
         Err(_) => error!("Channel closed!"),
     }
 
-    if let Ok(message) = output.recv() {
-        match message {
+    if let Ok(signed) = output.recv() {
+        match signed.message {
             PoLMessage::VDFProofAndCap { proof, cap: _ } => {
-                if proof.verify() {
+                if proof.verify::<RsaGroup>() {
                     info!("VDF ready!")
                 } else {
                     error!("Our VDF proof was not correct!")
@@ -100,6 +243,12 @@ fn main() {
         error!("Channel closed!");
     }
 
-    //   // Stop all workers, stop the node, cleanup and return.
-    //   ctx.stop().await
+    // The demo stops before the Proof of Latency is signed, closing the
+    // channel the prover waits on
+    drop(input);
+    match session.join() {
+        Ok(Ok(proof)) => info!("Proof of Latency finished: {:?}", proof),
+        Ok(Err(err)) => info!("Proof of Latency ended: {}", err),
+        Err(_) => error!("The PoL state machine panicked"),
+    }
 }
@@ -0,0 +1,704 @@
+//! Latency measurements among a set of peers. The Coordinator asks the
+//! PeerNode of every peer to prove its latency to the peers after it, a
+//! bounded number of sessions at a time, and collects the proofs into a
+//! LatencyMatrix signed by the coordinator.
+use crate::error::PoLError;
+use crate::identity::{self, Identity, SignedPoLMessage, Transcript};
+use crate::latency::LatencyProof;
+use crate::store::unix_millis;
+use crate::transport::{TcpTransport, Transport};
+use crate::vdf::group::{RsaGroup, VdfGroup};
+use crate::{MessageKind, PoLMessage, PoLRole, ProofOfLatency, Timeouts};
+use ed25519_dalek::PublicKey;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Fewest peers a matrix is measured among, as in testground/manifest.toml
+pub const MIN_PEERS: usize = 2;
+
+/// Most peers a matrix is measured among, as in testground/manifest.toml
+pub const MAX_PEERS: usize = 20;
+
+/// Sessions run at once unless set with Coordinator::with_max_sessions. Each
+/// session evaluates two VDFs, each in its own thread.
+pub const DEFAULT_MAX_SESSIONS: usize = 2;
+
+/// Connections a PeerNode serves at once unless set with
+/// PeerNode::with_max_sessions. A request of the coordinator takes one on the
+/// proving peer and the session it asks for one on the verifying peer.
+pub const DEFAULT_PEER_SESSIONS: usize = 4;
+
+/// How long after it was made a peer takes a request of its coordinator
+pub const REQUEST_LIFETIME: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatrixError {
+    /// The number of peers is not within MIN_PEERS..=MAX_PEERS
+    PeerCount { count: usize },
+    /// The threads for the sessions couldn't be started
+    ThreadPool { reason: String },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixError::PeerCount { count } => write!(
+                f,
+                "A latency matrix needs {} to {} peers, got {}",
+                MIN_PEERS, MAX_PEERS, count
+            ),
+            MatrixError::ThreadPool { reason } => {
+                write!(f, "Couldn't start the sessions: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+/// The outcome of the Proof of Latency between two peers, given by their
+/// indices in the matrix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairSession {
+    pub prover: usize,
+    pub verifier: usize,
+    pub result: Result<LatencyProof, PoLError>,
+}
+
+/// Proven latencies between every pair of peers, signed by the coordinator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyMatrix {
+    /// Public keys of the peers, indexing the rows and the columns
+    pub peers: Vec<Vec<u8>>,
+    /// Latency bounds in iterations, symmetric with zeros on the diagonal.
+    /// None where the session between the peers failed.
+    pub latencies: Vec<Vec<Option<u64>>>,
+    pub sessions: Vec<PairSession>,
+    pub key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl LatencyMatrix {
+    /// Latency between the peers at indices a and b
+    pub fn latency(&self, a: usize, b: usize) -> Option<u64> {
+        self.latencies.get(a)?.get(b).copied().flatten()
+    }
+
+    /// The digest the coordinator signs, covering the peers, the latencies
+    /// and the outcome of every session
+    pub fn digest(&self) -> [u8; 32] {
+        let mut transcript = Transcript::new("LatencyMatrix");
+        transcript.field(&(self.peers.len() as u64).to_be_bytes());
+        for peer in self.peers.iter() {
+            transcript.field(peer);
+        }
+        for latency in self.latencies.iter().flatten() {
+            match latency {
+                Some(latency) => transcript.field(&latency.to_be_bytes()),
+                None => transcript.field(&[]),
+            };
+        }
+        transcript.field(&(self.sessions.len() as u64).to_be_bytes());
+        for session in self.sessions.iter() {
+            transcript
+                .field(&(session.prover as u64).to_be_bytes())
+                .field(&(session.verifier as u64).to_be_bytes());
+            match &session.result {
+                Ok(proof) => transcript
+                    .field(&proof.digest())
+                    .field(&proof.prover_signature)
+                    .field(&proof.verifier_signature),
+                Err(err) => transcript.field(err.to_string().as_bytes()),
+            };
+        }
+        transcript.finish()
+    }
+
+    pub fn verify_signature(&self) -> Result<(), PoLError> {
+        identity::verify_signature(&self.key, &self.digest(), &self.signature)
+            .map(|_| ())
+    }
+
+    /// Checks the signature of the coordinator and every proof in group
+    /// against the peers and the latencies of the matrix
+    pub fn verify<G: VdfGroup>(&self, group: &G) -> Result<(), PoLError> {
+        self.verify_signature()?;
+        for session in self.sessions.iter() {
+            let proof = match &session.result {
+                Ok(proof) => proof,
+                Err(_) => continue,
+            };
+            let peers = (
+                self.peers.get(session.prover),
+                self.peers.get(session.verifier),
+            );
+            if peers != (Some(&proof.prover_key), Some(&proof.verifier_key)) {
+                return Err(PoLError::InvalidSignature);
+            }
+            let latency = Some(proof.verify(group)?);
+            if self.latency(session.prover, session.verifier) != latency
+                || self.latency(session.verifier, session.prover) != latency
+            {
+                return Err(PoLError::InvalidProof);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A peer of a latency matrix: its public key and the address its PeerNode
+/// listens on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub key: Vec<u8>,
+    pub address: SocketAddr,
+}
+
+/// Runs a Proof of Latency between every pair of peers in the group G. The
+/// coordinator only asks the provers to run the sessions, the peers signing
+/// the proofs with their own identities.
+#[derive(Debug, Clone)]
+pub struct Coordinator<G: VdfGroup = RsaGroup> {
+    group: G,
+    upper_bound: u64,
+    max_sessions: usize,
+    timeouts: Timeouts,
+}
+
+impl<G: VdfGroup> Coordinator<G> {
+    /// Coordinator whose provers evaluate upper_bound iterations
+    pub fn new(group: G, upper_bound: u64) -> Self {
+        Self {
+            group,
+            upper_bound,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Runs at most max_sessions sessions at once
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions.max(1);
+        self
+    }
+
+    /// Gives up on a session when the peers take longer than the timeouts of
+    /// all its states together
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Measures the latency between every pair of peers, the peer with the
+    /// lower index proving, and signs the matrix as coordinator
+    pub fn run(
+        &self,
+        peers: &[Peer],
+        coordinator: &Identity,
+    ) -> Result<LatencyMatrix, MatrixError> {
+        if peers.len() < MIN_PEERS || peers.len() > MAX_PEERS {
+            return Err(MatrixError::PeerCount { count: peers.len() });
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.max_sessions)
+            .build()
+            .map_err(|err| MatrixError::ThreadPool {
+                reason: err.to_string(),
+            })?;
+
+        let pairs: Vec<(usize, usize)> = (0..peers.len())
+            .flat_map(|a| (a + 1..peers.len()).map(move |b| (a, b)))
+            .collect();
+        let sessions: Vec<PairSession> = pool.install(|| {
+            pairs
+                .par_iter()
+                .map(|&(prover, verifier)| PairSession {
+                    prover,
+                    verifier,
+                    result: self.run_pair(
+                        &peers[prover],
+                        &peers[verifier],
+                        coordinator,
+                    ),
+                })
+                .collect()
+        });
+
+        let mut latencies = vec![vec![None; peers.len()]; peers.len()];
+        for (index, row) in latencies.iter_mut().enumerate() {
+            row[index] = Some(0);
+        }
+        for session in sessions.iter() {
+            let latency = match &session.result {
+                Ok(proof) => proof.verify(&self.group).ok(),
+                Err(_) => None,
+            };
+            latencies[session.prover][session.verifier] = latency;
+            latencies[session.verifier][session.prover] = latency;
+        }
+
+        let mut matrix = LatencyMatrix {
+            peers: peers.iter().map(|peer| peer.key.clone()).collect(),
+            latencies,
+            sessions,
+            key: coordinator.public_key().to_bytes().to_vec(),
+            signature: Vec::new(),
+        };
+        matrix.signature = coordinator.sign(&matrix.digest());
+        Ok(matrix)
+    }
+
+    /// Asks the prover to prove its latency to the verifier, waiting for the
+    /// proof in the calling thread. The proof has to be signed by both peers.
+    fn run_pair(
+        &self,
+        prover: &Peer,
+        verifier: &Peer,
+        coordinator: &Identity,
+    ) -> Result<LatencyProof, PoLError> {
+        let mut transport = TcpTransport::connect(prover.address)
+            .map_err(|_| PoLError::ChannelClosed)?;
+        let nonce = rand::random::<u64>();
+        let request = PoLMessage::ProveTo {
+            address: verifier.address.to_string(),
+            verifier: verifier.key.clone(),
+            upper_bound: self.upper_bound,
+            nonce,
+            timestamp: unix_millis(),
+        };
+        transport.send(SignedPoLMessage::new(request, coordinator))?;
+        let deadline =
+            session_timeout(&self.timeouts).map(|t| Instant::now() + t);
+        let reply = transport.recv(deadline);
+        transport.close();
+
+        let reply = reply?;
+        if reply.verify()?.to_bytes()[..] != prover.key[..] {
+            return Err(PoLError::InvalidSignature);
+        }
+        let proof = match reply.message {
+            PoLMessage::ProvedTo { nonce: echoed, .. }
+            | PoLMessage::ProveToFailed { nonce: echoed, .. }
+                if echoed != nonce =>
+            {
+                return Err(PoLError::Replayed)
+            }
+            PoLMessage::ProvedTo { proof, .. } => proof,
+            PoLMessage::ProveToFailed { error, .. } => {
+                return Err(PoLError::aborted_by_peer(error))
+            }
+            message => {
+                return Err(PoLError::ProtocolViolation {
+                    expected: MessageKind::ProvedTo,
+                    received: message.kind(),
+                })
+            }
+        };
+        if (&proof.prover_key, &proof.verifier_key)
+            != (&prover.key, &verifier.key)
+        {
+            return Err(PoLError::InvalidSignature);
+        }
+        proof.verify(&self.group)?;
+        Ok(proof)
+    }
+}
+
+/// How long a whole session may take, None if a state may wait forever
+fn session_timeout(timeouts: &Timeouts) -> Option<Duration> {
+    Some(
+        timeouts.generator_part?
+            + timeouts.generator_part_and_cap?
+            + timeouts.evaluation?
+            + timeouts.vdf_proof_and_cap?
+            + timeouts.proof_of_latency?,
+    )
+}
+
+/// A peer of latency matrices listening for TCP connections. Provers
+/// connecting to it are verified, and when its coordinator asks, it proves
+/// its latency to another peer and sends the proof back.
+#[derive(Debug)]
+pub struct PeerNode<G: VdfGroup = RsaGroup> {
+    group: G,
+    identity: Identity,
+    coordinator: Vec<u8>,
+    timeouts: Timeouts,
+    // Connections beyond this many are closed as soon as they are accepted
+    max_sessions: usize,
+    sessions: AtomicUsize,
+    // Nonces of the requests taken within REQUEST_LIFETIME, with their
+    // timestamps
+    nonces: Mutex<HashMap<u64, u64>>,
+}
+
+impl<G: VdfGroup> PeerNode<G> {
+    /// Peer taking requests only from the coordinator with the public key
+    pub fn new(group: G, identity: Identity, coordinator: &PublicKey) -> Self {
+        Self {
+            group,
+            identity,
+            coordinator: coordinator.to_bytes().to_vec(),
+            timeouts: Timeouts::default(),
+            max_sessions: DEFAULT_PEER_SESSIONS,
+            sessions: AtomicUsize::new(0),
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Aborts sessions with states taking longer than the timeouts
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Serves at most max_sessions connections at once, turning away the
+    /// ones beyond
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    /// Serves the connections accepted from listener, each in a thread of
+    /// its own, until accepting fails
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let node = Arc::new(self);
+        loop {
+            let mut transport = TcpTransport::accept(&listener)?;
+            if node.sessions.load(Ordering::SeqCst) >= node.max_sessions {
+                warn!("Too many sessions, turning away a connection");
+                transport.close();
+                continue;
+            }
+            node.sessions.fetch_add(1, Ordering::SeqCst);
+            let node = node.clone();
+            thread::spawn(move || {
+                node.handle(transport);
+                node.sessions.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    /// Answers a request of the coordinator, or verifies a prover starting a
+    /// session with its generator part
+    fn handle(&self, mut transport: TcpTransport) {
+        let deadline = self.timeouts.generator_part.map(|t| Instant::now() + t);
+        let first = match transport.recv(deadline) {
+            Ok(first) => first,
+            Err(err) => {
+                debug!("Dropping a connection: {}", err);
+                return;
+            }
+        };
+        let (address, verifier, upper_bound, nonce, timestamp) =
+            match &first.message {
+                PoLMessage::ProveTo {
+                    address,
+                    verifier,
+                    upper_bound,
+                    nonce,
+                    timestamp,
+                } => (
+                    address.clone(),
+                    verifier.clone(),
+                    *upper_bound,
+                    *nonce,
+                    *timestamp,
+                ),
+                _ => {
+                    if let Err(err) = self.verify(Replay {
+                        first: Some(first),
+                        inner: transport,
+                    }) {
+                        debug!("Verifying a prover failed: {}", err);
+                    }
+                    return;
+                }
+            };
+
+        let result = match first.verify() {
+            Ok(signer) if signer.to_bytes()[..] == self.coordinator[..] => self
+                .take_request(nonce, timestamp)
+                .and_then(|_| self.prove(&address, &verifier, upper_bound)),
+            _ => Err(PoLError::InvalidSignature),
+        };
+        let reply = match result {
+            Ok(proof) => PoLMessage::ProvedTo { nonce, proof },
+            Err(error) => PoLMessage::ProveToFailed { nonce, error },
+        };
+        if transport
+            .send(SignedPoLMessage::new(reply, &self.identity))
+            .is_err()
+        {
+            warn!("Couldn't reply to the coordinator");
+        }
+        transport.close();
+    }
+
+    /// Takes a request of the coordinator made within REQUEST_LIFETIME, once
+    fn take_request(&self, nonce: u64, timestamp: u64) -> Result<(), PoLError> {
+        let now = unix_millis();
+        let lifetime = REQUEST_LIFETIME.as_millis() as u64;
+        if timestamp.saturating_add(lifetime) < now
+            || timestamp > now.saturating_add(lifetime)
+        {
+            return Err(PoLError::Replayed);
+        }
+        let mut nonces = match self.nonces.lock() {
+            Ok(nonces) => nonces,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        // Older requests are refused by their timestamp
+        nonces.retain(|_, taken| taken.saturating_add(lifetime) >= now);
+        match nonces.insert(nonce, timestamp) {
+            Some(_) => Err(PoLError::Replayed),
+            None => Ok(()),
+        }
+    }
+
+    fn verify(&self, transport: Replay) -> Result<LatencyProof, PoLError> {
+        let session = ProofOfLatency::default()
+            .init(self.group.clone(), u64::MAX)
+            .with_identity(self.identity.clone())
+            .with_timeouts(self.timeouts)
+            .with_transport(transport)
+            .start_async(PoLRole::Verifier)?;
+        async_std::task::block_on(session)
+    }
+
+    fn prove(
+        &self,
+        address: &str,
+        verifier: &[u8],
+        upper_bound: u64,
+    ) -> Result<LatencyProof, PoLError> {
+        let verifier = PublicKey::from_bytes(verifier)
+            .map_err(|_| PoLError::InvalidSignature)?;
+        let transport = TcpTransport::connect(address)
+            .map_err(|_| PoLError::ChannelClosed)?;
+        let session = ProofOfLatency::default()
+            .init(self.group.clone(), upper_bound)
+            .with_identity(self.identity.clone())
+            .with_peer(verifier)
+            .with_timeouts(self.timeouts)
+            .with_transport(transport)
+            .start_async(PoLRole::Prover)?;
+        async_std::task::block_on(session)
+    }
+}
+
+/// A transport giving back the message already read from it first
+#[derive(Debug)]
+struct Replay {
+    first: Option<SignedPoLMessage>,
+    inner: TcpTransport,
+}
+
+impl Transport for Replay {
+    fn send(&mut self, message: SignedPoLMessage) -> Result<(), PoLError> {
+        self.inner.send(message)
+    }
+
+    fn recv(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<SignedPoLMessage, PoLError> {
+        match self.first.take() {
+            Some(first) => Ok(first),
+            None => self.inner.recv(deadline),
+        }
+    }
+
+    fn close(&mut self) {
+        self.inner.close()
+    }
+
+    fn peer_address(&self) -> Option<SocketAddr> {
+        self.inner.peer_address()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::bigint::Int;
+    use crate::RSA_2048;
+    use std::str::FromStr;
+
+    fn coordinator() -> Coordinator {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        Coordinator::new(RsaGroup::new(modulus), 100)
+    }
+
+    /// Peer nodes on loopback taking requests from signer. They keep
+    /// listening until the test process ends.
+    fn peers(count: usize, signer: &Identity) -> Vec<Peer> {
+        (0..count)
+            .map(|_| {
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                let identity = Identity::generate();
+                let peer = Peer {
+                    key: identity.public_key().to_bytes().to_vec(),
+                    address: listener.local_addr().unwrap(),
+                };
+                let node = PeerNode::new(
+                    coordinator().group,
+                    identity,
+                    &signer.public_key(),
+                );
+                thread::spawn(move || node.serve(listener));
+                peer
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_pair_of_peers_is_measured() {
+        let signer = Identity::generate();
+        let peers = peers(3, &signer);
+        let matrix = coordinator().run(&peers, &signer).unwrap();
+
+        assert_eq!(matrix.sessions.len(), 3);
+        assert!(matrix.verify(&coordinator().group).is_ok());
+        for a in 0..3 {
+            assert_eq!(matrix.latency(a, a), Some(0));
+            for b in 0..3 {
+                assert!(matrix.latency(a, b).is_some());
+                assert_eq!(matrix.latency(a, b), matrix.latency(b, a));
+            }
+        }
+
+        let mut forged = matrix;
+        forged.latencies[0][1] = Some(0);
+        forged.latencies[1][0] = Some(0);
+        assert_eq!(forged.verify_signature(), Err(PoLError::InvalidSignature));
+        forged.signature = signer.sign(&forged.digest());
+        assert_eq!(
+            forged.verify(&coordinator().group),
+            Err(PoLError::InvalidProof)
+        );
+    }
+
+    #[test]
+    fn peers_only_prove_for_their_coordinator() {
+        let peers = peers(2, &Identity::generate());
+        let matrix = coordinator().run(&peers, &Identity::generate()).unwrap();
+        assert_eq!(
+            matrix.sessions[0].result,
+            Err(PoLError::aborted_by_peer(PoLError::InvalidSignature))
+        );
+        assert_eq!(matrix.latency(0, 1), None);
+    }
+
+    /// Sends the request to the peer node, returning its reply
+    fn ask(peer: &Peer, request: &SignedPoLMessage) -> PoLMessage {
+        let mut transport = TcpTransport::connect(peer.address).unwrap();
+        transport.send(request.clone()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        transport.recv(Some(deadline)).unwrap().message
+    }
+
+    #[test]
+    fn peers_refuse_replayed_requests() {
+        let signer = Identity::generate();
+        let peers = peers(2, &signer);
+        let request = |timestamp| {
+            SignedPoLMessage::new(
+                PoLMessage::ProveTo {
+                    address: peers[1].address.to_string(),
+                    verifier: peers[1].key.clone(),
+                    upper_bound: 100,
+                    nonce: 7,
+                    timestamp,
+                },
+                &signer,
+            )
+        };
+
+        let fresh = request(unix_millis());
+        assert!(matches!(
+            ask(&peers[0], &fresh),
+            PoLMessage::ProvedTo { nonce: 7, .. }
+        ));
+        let failed = PoLMessage::ProveToFailed {
+            nonce: 7,
+            error: PoLError::Replayed,
+        };
+        assert_eq!(ask(&peers[0], &fresh), failed);
+        let stale = unix_millis() - 2 * REQUEST_LIFETIME.as_millis() as u64;
+        assert_eq!(ask(&peers[0], &request(stale)), failed);
+    }
+
+    #[test]
+    fn replies_to_other_requests_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let identity = Identity::generate();
+        let peer = Peer {
+            key: identity.public_key().to_bytes().to_vec(),
+            address: listener.local_addr().unwrap(),
+        };
+        // A peer answering with the reply to an earlier request
+        thread::spawn(move || {
+            let mut transport = TcpTransport::accept(&listener).unwrap();
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let nonce = match transport.recv(Some(deadline)).unwrap().message {
+                PoLMessage::ProveTo { nonce, .. } => nonce,
+                other => panic!("Expected a request, got {:?}", other),
+            };
+            let reply = PoLMessage::ProveToFailed {
+                nonce: nonce.wrapping_add(1),
+                error: PoLError::Timeout,
+            };
+            transport
+                .send(SignedPoLMessage::new(reply, &identity))
+                .unwrap();
+        });
+
+        let other = Peer {
+            key: Identity::generate().public_key().to_bytes().to_vec(),
+            address: peer.address,
+        };
+        assert_eq!(
+            coordinator().run_pair(&peer, &other, &Identity::generate()),
+            Err(PoLError::Replayed)
+        );
+    }
+
+    #[test]
+    fn peer_nodes_turn_away_connections_beyond_their_sessions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let node = PeerNode::new(
+            coordinator().group,
+            Identity::generate(),
+            &Identity::generate().public_key(),
+        )
+        .with_max_sessions(1);
+        thread::spawn(move || node.serve(listener));
+
+        // The first connection keeps its session waiting for a message
+        let mut first = TcpTransport::connect(address).unwrap();
+        let mut second = TcpTransport::connect(address).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        assert_eq!(second.recv(Some(deadline)), Err(PoLError::ChannelClosed));
+        first.close();
+    }
+
+    #[test]
+    fn peer_count_is_checked() {
+        let signer = Identity::generate();
+        let peers = peers(1, &signer);
+        assert_eq!(
+            coordinator().run(&peers, &signer),
+            Err(MatrixError::PeerCount { count: 1 })
+        );
+    }
+}
@@ -0,0 +1,605 @@
+//! Append-only log of Proofs of Latency committed to by a Merkle tree over
+//! blake3, built like the Certificate Transparency logs of RFC 6962. The
+//! node publishes signed tree heads, and hands auditors inclusion proofs
+//! showing that a proof is in a committed tree and consistency proofs showing
+//! that a later tree only appended to an earlier one.
+use crate::archive::{decode, encode};
+use crate::error::PoLError;
+use crate::identity::{self, Identity, Transcript};
+use crate::latency::LatencyProof;
+use ed25519_dalek::{PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use sled::transaction::{
+    ConflictableTransactionError, TransactionError, Transactional,
+};
+use std::io;
+use std::path::Path;
+
+pub type Hash = [u8; 32];
+
+/// Hash of a proof as a leaf of the tree. The digest covers everything in
+/// the proof but the signatures, which are hashed after it.
+pub fn leaf_hash(proof: &LatencyProof) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0]);
+    hasher.update(&proof.digest());
+    hasher.update(&proof.prover_signature);
+    hasher.update(&proof.verifier_signature);
+    *hasher.finalize().as_bytes()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[1]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+/// Largest power of two smaller than n, for n > 1
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+/// Root of the tree over the leaf hashes
+fn root(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => *blake3::hash(&[]).as_bytes(),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// Audit path of the leaf at index, from the leaf up
+fn path(index: usize, leaves: &[Hash]) -> Vec<Hash> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split(leaves.len());
+    let (mut path, sibling) = if index < k {
+        (path(index, &leaves[..k]), root(&leaves[k..]))
+    } else {
+        (path(index - k, &leaves[k..]), root(&leaves[..k]))
+    };
+    path.push(sibling);
+    path
+}
+
+/// Consistency proof of the first old_size leaves with all of them. whole
+/// tells whether the old tree is the whole subtree, in which case the
+/// verifier already knows its root.
+fn subproof(old_size: usize, leaves: &[Hash], whole: bool) -> Vec<Hash> {
+    if old_size == leaves.len() {
+        return if whole {
+            Vec::new()
+        } else {
+            vec![root(leaves)]
+        };
+    }
+    let k = split(leaves.len());
+    let (mut proof, sibling) = if old_size <= k {
+        (subproof(old_size, &leaves[..k], whole), root(&leaves[k..]))
+    } else {
+        (
+            subproof(old_size - k, &leaves[k..], false),
+            root(&leaves[..k]),
+        )
+    };
+    proof.push(sibling);
+    proof
+}
+
+/// Shows that the leaf at index is in the tree of size leaves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub index: u64,
+    pub size: u64,
+    pub path: Vec<Hash>,
+}
+
+impl InclusionProof {
+    /// Checks that leaf is at index in the tree with the root
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        if self.index >= self.size {
+            return false;
+        }
+        let mut index = self.index;
+        let mut last = self.size - 1;
+        let mut hash = *leaf;
+        for sibling in self.path.iter() {
+            if last == 0 {
+                return false;
+            }
+            if index & 1 == 1 || index == last {
+                hash = node_hash(sibling, &hash);
+                // Skip the levels where the node has no sibling
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                hash = node_hash(&hash, sibling);
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+        last == 0 && hash == *root
+    }
+}
+
+/// Shows that the tree of new_size leaves only appended to the tree of
+/// old_size leaves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyProof {
+    pub old_size: u64,
+    pub new_size: u64,
+    pub path: Vec<Hash>,
+}
+
+impl ConsistencyProof {
+    pub fn verify(&self, old_root: &Hash, new_root: &Hash) -> bool {
+        if self.old_size > self.new_size {
+            return false;
+        }
+        if self.old_size == self.new_size {
+            return self.path.is_empty() && old_root == new_root;
+        }
+        // Every tree extends the empty one
+        if self.old_size == 0 {
+            return self.path.is_empty();
+        }
+
+        // A power of two sized old tree is a subtree of the new one, and its
+        // root is left out of the proof
+        let mut path = self.path.iter();
+        let first = if self.old_size.is_power_of_two() {
+            *old_root
+        } else {
+            match path.next() {
+                Some(first) => *first,
+                None => return false,
+            }
+        };
+        let mut index = self.old_size - 1;
+        let mut last = self.new_size - 1;
+        while index & 1 == 1 {
+            index >>= 1;
+            last >>= 1;
+        }
+
+        let mut old_hash = first;
+        let mut new_hash = first;
+        for sibling in path {
+            if last == 0 {
+                return false;
+            }
+            if index & 1 == 1 || index == last {
+                old_hash = node_hash(sibling, &old_hash);
+                new_hash = node_hash(sibling, &new_hash);
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                new_hash = node_hash(&new_hash, sibling);
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+        last == 0 && old_hash == *old_root && new_hash == *new_root
+    }
+}
+
+fn invalid_data(message: &str) -> sled::Error {
+    sled::Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Reads the stored leaf at index
+fn to_hash(index: u64, value: &[u8]) -> sled::Result<Hash> {
+    let mut hash = [0u8; 32];
+    if value.len() != hash.len() {
+        return Err(invalid_data(&format!(
+            "Leaf {} of the log is {} bytes long",
+            index,
+            value.len()
+        )));
+    }
+    hash.copy_from_slice(value);
+    Ok(hash)
+}
+
+fn to_index(value: &[u8]) -> Option<u64> {
+    let mut index = [0u8; 8];
+    if value.len() != index.len() {
+        return None;
+    }
+    index.copy_from_slice(value);
+    Some(u64::from_be_bytes(index))
+}
+
+/// The root of the log after size proofs, signed by the node keeping it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeHead {
+    pub epoch: u64,
+    pub size: u64,
+    pub root: Hash,
+    pub key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl TreeHead {
+    /// The digest the node signs
+    pub fn digest(&self) -> [u8; 32] {
+        Transcript::new("TreeHead")
+            .field(&self.epoch.to_be_bytes())
+            .field(&self.size.to_be_bytes())
+            .field(&self.root)
+            .field(&self.key)
+            .finish()
+    }
+
+    pub fn verify_signature(&self) -> Result<(), PoLError> {
+        identity::verify_signature(&self.key, &self.digest(), &self.signature)
+            .map(|_| ())
+    }
+
+    // Stored as the size, the root, the key and the signature
+    fn to_bytes(&self) -> Vec<u8> {
+        [
+            &self.size.to_be_bytes()[..],
+            &self.root,
+            &self.key,
+            &self.signature,
+        ]
+        .concat()
+    }
+
+    fn from_bytes(epoch: u64, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 8 + 32 + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH {
+            return None;
+        }
+        let mut size = [0u8; 8];
+        size.copy_from_slice(&bytes[..8]);
+        let mut root = [0u8; 32];
+        root.copy_from_slice(&bytes[8..40]);
+        let (key, signature) = bytes[40..].split_at(PUBLIC_KEY_LENGTH);
+        Some(Self {
+            epoch,
+            size: u64::from_be_bytes(size),
+            root,
+            key: key.to_vec(),
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+/// Key of the size of the log in its meta tree
+const SIZE_KEY: &[u8] = b"size";
+
+/// Append-only log of Proofs of Latency in sled. Proofs are appended as they
+/// are, so they should be verified first. The leaf hashes are kept in memory
+/// for building the proofs, and only catch up with proofs appended by other
+/// logs on the same database when this one appends.
+#[derive(Debug)]
+pub struct ProofLog {
+    proofs: sled::Tree,
+    leaves: sled::Tree,
+    heads: sled::Tree,
+    meta: sled::Tree,
+    hashes: Vec<Hash>,
+}
+
+impl ProofLog {
+    /// Opens the log from the sled database at path
+    pub fn open<P: AsRef<Path>>(path: P) -> sled::Result<Self> {
+        Self::from_db(&sled::open(path)?)
+    }
+
+    /// Opens the log from an already open database. Fails if a stored leaf
+    /// isn't a hash.
+    pub fn from_db(db: &sled::Db) -> sled::Result<Self> {
+        let leaves = db.open_tree("log/leaves")?;
+        let mut hashes = Vec::with_capacity(leaves.len());
+        for (index, value) in leaves.iter().values().enumerate() {
+            hashes.push(to_hash(index as u64, &value?)?);
+        }
+        // Logs written before the size was stored are as long as their leaves
+        let meta = db.open_tree("log/meta")?;
+        let size = (hashes.len() as u64).to_be_bytes();
+        let _ = meta.compare_and_swap(
+            SIZE_KEY,
+            None as Option<&[u8]>,
+            Some(&size[..]),
+        )?;
+        Ok(Self {
+            proofs: db.open_tree("log/proofs")?,
+            leaves,
+            heads: db.open_tree("log/heads")?,
+            meta,
+            hashes,
+        })
+    }
+
+    /// Appends the proof, returning its index. The index is taken from the
+    /// size stored in the same transaction, so logs sharing the database
+    /// never append two proofs at the same index.
+    pub fn append(&mut self, proof: &LatencyProof) -> sled::Result<u64> {
+        let leaf = leaf_hash(proof);
+        let value = encode(proof);
+        let index = (&self.proofs, &self.leaves, &self.meta)
+            .transaction(|(proofs, leaves, meta)| {
+                let index = match meta.get(SIZE_KEY)? {
+                    Some(size) => to_index(&size).ok_or_else(|| {
+                        ConflictableTransactionError::Abort(invalid_data(
+                            "The size of the proof log is corrupted",
+                        ))
+                    })?,
+                    None => 0,
+                };
+                proofs.insert(&index.to_be_bytes(), value.as_slice())?;
+                leaves.insert(&index.to_be_bytes(), &leaf[..])?;
+                meta.insert(SIZE_KEY, &(index + 1).to_be_bytes())?;
+                Ok(index)
+            })
+            .map_err(|err: TransactionError<sled::Error>| match err {
+                TransactionError::Abort(err)
+                | TransactionError::Storage(err) => err,
+            })?;
+        self.leaves.flush()?;
+        while self.len() < index {
+            let missing = self.len();
+            let value = self.leaves.get(missing.to_be_bytes())?;
+            let value = value.ok_or_else(|| {
+                invalid_data(&format!("Leaf {} of the log is missing", missing))
+            })?;
+            self.hashes.push(to_hash(missing, &value)?);
+        }
+        self.hashes.push(leaf);
+        Ok(index)
+    }
+
+    pub fn get(&self, index: u64) -> sled::Result<Option<LatencyProof>> {
+        Ok(self
+            .proofs
+            .get(index.to_be_bytes())?
+            .and_then(|value| decode(&value)))
+    }
+
+    /// Index of the proof in the log
+    pub fn position(&self, proof: &LatencyProof) -> Option<u64> {
+        let leaf = leaf_hash(proof);
+        self.hashes
+            .iter()
+            .position(|hash| *hash == leaf)
+            .map(|index| index as u64)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.hashes.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Root of the log as it was after size proofs
+    pub fn root_at(&self, size: u64) -> Option<Hash> {
+        self.leaves_at(size).map(root)
+    }
+
+    pub fn root(&self) -> Hash {
+        root(&self.hashes)
+    }
+
+    fn leaves_at(&self, size: u64) -> Option<&[Hash]> {
+        if size > self.len() {
+            return None;
+        }
+        Some(&self.hashes[..size as usize])
+    }
+
+    /// Signs and stores the current root as the next epoch
+    pub fn commit(&self, identity: &Identity) -> sled::Result<TreeHead> {
+        let epoch = match self.latest_head()? {
+            Some(head) => head.epoch + 1,
+            None => 0,
+        };
+        let mut head = TreeHead {
+            epoch,
+            size: self.len(),
+            root: self.root(),
+            key: identity.public_key().to_bytes().to_vec(),
+            signature: Vec::new(),
+        };
+        head.signature = identity.sign(&head.digest());
+        self.heads.insert(epoch.to_be_bytes(), head.to_bytes())?;
+        self.heads.flush()?;
+        Ok(head)
+    }
+
+    pub fn head(&self, epoch: u64) -> sled::Result<Option<TreeHead>> {
+        Ok(self
+            .heads
+            .get(epoch.to_be_bytes())?
+            .and_then(|value| TreeHead::from_bytes(epoch, &value)))
+    }
+
+    pub fn latest_head(&self) -> sled::Result<Option<TreeHead>> {
+        match self.heads.last()? {
+            Some((key, value)) if key.len() == 8 => {
+                let mut epoch = [0u8; 8];
+                epoch.copy_from_slice(&key);
+                Ok(TreeHead::from_bytes(u64::from_be_bytes(epoch), &value))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Proves that the proof at index is in the log of size proofs
+    pub fn inclusion_proof(
+        &self,
+        index: u64,
+        size: u64,
+    ) -> Option<InclusionProof> {
+        if index >= size {
+            return None;
+        }
+        let leaves = self.leaves_at(size)?;
+        Some(InclusionProof {
+            index,
+            size,
+            path: path(index as usize, leaves),
+        })
+    }
+
+    /// Proves that the log of new_size proofs extends the one of old_size
+    pub fn consistency_proof(
+        &self,
+        old_size: u64,
+        new_size: u64,
+    ) -> Option<ConsistencyProof> {
+        if old_size > new_size {
+            return None;
+        }
+        let leaves = self.leaves_at(new_size)?;
+        let path = if old_size == 0 {
+            Vec::new()
+        } else {
+            subproof(old_size as usize, leaves, true)
+        };
+        Some(ConsistencyProof {
+            old_size,
+            new_size,
+            path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::latency::made_up_proof;
+
+    fn log() -> ProofLog {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        ProofLog::from_db(&db).unwrap()
+    }
+
+    /// A proof told apart by i
+    fn proof(i: u8) -> LatencyProof {
+        made_up_proof(i, i)
+    }
+
+    #[test]
+    fn every_proof_is_included_in_every_tree() {
+        let mut log = log();
+        for i in 0..13 {
+            assert_eq!(log.append(&proof(i)).unwrap(), i as u64);
+        }
+        for size in 1..=log.len() {
+            let root = log.root_at(size).unwrap();
+            for index in 0..size {
+                let inclusion = log.inclusion_proof(index, size).unwrap();
+                let leaf = leaf_hash(&proof(index as u8));
+                assert!(inclusion.verify(&leaf, &root));
+                assert!(!inclusion.verify(&leaf_hash(&proof(100)), &root));
+                if size > 1 {
+                    let mut moved = inclusion.clone();
+                    moved.index = (index + 1) % size;
+                    assert!(!moved.verify(&leaf, &root));
+                }
+            }
+        }
+        assert!(log.inclusion_proof(13, 13).is_none());
+        assert_eq!(log.position(&proof(7)), Some(7));
+        assert_eq!(log.position(&proof(100)), None);
+    }
+
+    #[test]
+    fn later_trees_are_consistent_with_earlier_ones() {
+        let mut log = log();
+        for i in 0..11 {
+            log.append(&proof(i)).unwrap();
+        }
+        for new_size in 0..=log.len() {
+            let new_root = log.root_at(new_size).unwrap();
+            for old_size in 0..=new_size {
+                let old_root = log.root_at(old_size).unwrap();
+                let consistency =
+                    log.consistency_proof(old_size, new_size).unwrap();
+                assert!(consistency.verify(&old_root, &new_root));
+                if 0 < old_size && old_size < new_size {
+                    let forged = root(&[leaf_hash(&proof(100))]);
+                    assert!(!consistency.verify(&forged, &new_root));
+                    assert!(!consistency.verify(&old_root, &forged));
+                }
+            }
+        }
+        assert!(log.consistency_proof(5, 4).is_none());
+        assert!(log.consistency_proof(5, 12).is_none());
+    }
+
+    #[test]
+    fn committed_heads_survive_reopening() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let identity = Identity::generate();
+        let mut log = ProofLog::from_db(&db).unwrap();
+        log.append(&proof(0)).unwrap();
+        let first = log.commit(&identity).unwrap();
+        log.append(&proof(1)).unwrap();
+        log.append(&proof(2)).unwrap();
+        let second = log.commit(&identity).unwrap();
+        assert_eq!((first.epoch, second.epoch), (0, 1));
+        assert!(second.verify_signature().is_ok());
+
+        let log = ProofLog::from_db(&db).unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.get(2).unwrap(), Some(proof(2)));
+        assert_eq!(log.head(0).unwrap(), Some(first.clone()));
+        assert_eq!(log.latest_head().unwrap(), Some(second.clone()));
+
+        // An auditor holding both heads checks a proof of the first epoch
+        let inclusion = log.inclusion_proof(0, first.size).unwrap();
+        assert!(inclusion.verify(&leaf_hash(&proof(0)), &first.root));
+        let consistency =
+            log.consistency_proof(first.size, second.size).unwrap();
+        assert!(consistency.verify(&first.root, &second.root));
+
+        let mut forged = second;
+        forged.size = 2;
+        assert!(forged.verify_signature().is_err());
+    }
+
+    #[test]
+    fn logs_sharing_a_database_append_at_distinct_indices() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut first = ProofLog::from_db(&db).unwrap();
+        let mut second = ProofLog::from_db(&db).unwrap();
+        assert_eq!(first.append(&proof(0)).unwrap(), 0);
+        assert_eq!(second.append(&proof(1)).unwrap(), 1);
+        assert_eq!(first.append(&proof(2)).unwrap(), 2);
+
+        // The first log caught up with the proof appended by the second
+        assert_eq!(first.len(), 3);
+        assert_eq!(first.get(1).unwrap(), Some(proof(1)));
+        assert_eq!(first.root(), ProofLog::from_db(&db).unwrap().root());
+    }
+
+    #[test]
+    fn malformed_leaves_are_refused() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut log = ProofLog::from_db(&db).unwrap();
+        log.append(&proof(0)).unwrap();
+        db.open_tree("log/leaves")
+            .unwrap()
+            .insert(1u64.to_be_bytes(), &[0u8; 31][..])
+            .unwrap();
+        assert!(ProofLog::from_db(&db).is_err());
+    }
+}
@@ -0,0 +1,490 @@
+//! Running the protocol between Ockam nodes. A PoLWorker hosts the state
+//! machines of its sessions and relays their messages as frame::encode
+//! frames, the Verifier node accepting Provers behind a secure channel
+//! listener.
+use crate::error::PoLError;
+use crate::identity::{Identity, SignedPoLMessage};
+use crate::latency::LatencyProof;
+use crate::transport::frame::{self, FrameBuffer, MAX_FRAME_SIZE};
+use crate::vdf::calibration::Calibration;
+use crate::vdf::group::VdfGroup;
+use crate::{PoLMessage, PoLRole, ProofOfLatency, Timeouts};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use ockam::{
+    route, Address, Context, Result, Route, Routed, SecureChannel, Vault,
+    Worker, TCP,
+};
+use std::collections::HashMap;
+
+/// Address of the Verifier worker on the Verifier node
+pub const VERIFIER_WORKER: &str = "proof_of_latency";
+
+/// Address of the secure channel listener on the Verifier node
+pub const SECURE_CHANNEL_LISTENER: &str = "secure_channel_listener";
+
+/// Sessions a Verifier worker runs at once unless set with
+/// PoLWorker::with_max_sessions
+pub const DEFAULT_MAX_SESSIONS: usize = 16;
+
+/// Error domain and code of failures to start a session
+const ERROR_DOMAIN: &str = "PROOF_OF_LATENCY";
+const SESSION_ERROR: u32 = 1;
+
+/// A state machine and the route to its peer
+struct Session {
+    peer: Route,
+    // Messages from the peer, read by the state machine until the peer
+    // closes the session
+    incoming: Option<UnboundedSender<SignedPoLMessage>>,
+    // Whether the state machine has closed its transport
+    ended: bool,
+    // The Proof of Latency sent by a Prover, reported when its Verifier
+    // closes the session
+    proof: Option<LatencyProof>,
+}
+
+/// Ockam worker running Proofs of Latency with the peers routing messages
+/// to it. Every session has a relay context, the messages of the state
+/// machine coming back to the worker from it before they are sent to the
+/// peer, so that the peer replies to the worker. An empty message closes
+/// the session on the other side.
+pub struct PoLWorker<G: VdfGroup> {
+    role: PoLRole,
+    group: G,
+    upper_bound: u64,
+    identity: Identity,
+    calibration: Option<Calibration>,
+    timeouts: Timeouts,
+    // Provers beyond this many are turned away by a Verifier
+    max_sessions: usize,
+    // The Verifier of a Prover, Verifiers learning their Provers from their
+    // messages
+    verifier: Option<Route>,
+    // Sessions by the address of their relay
+    sessions: HashMap<Address, Session>,
+    // Relay addresses by the route to the peer
+    relays: HashMap<String, Address>,
+    // Finished Proofs of Latency, signed by both peers
+    proofs: UnboundedSender<LatencyProof>,
+}
+
+impl<G: VdfGroup> PoLWorker<G> {
+    /// Verifies the Provers messaging this worker, evaluating until their
+    /// caps arrive or Timeouts::default pass
+    pub fn verifier(
+        group: G,
+        identity: Identity,
+        proofs: UnboundedSender<LatencyProof>,
+    ) -> Self {
+        Self::new(PoLRole::Verifier, group, u64::MAX, identity, proofs)
+    }
+
+    /// Proves latency to the Verifier worker at the end of verifier, starting
+    /// when the worker starts
+    pub fn prover(
+        group: G,
+        upper_bound: u64,
+        identity: Identity,
+        verifier: Route,
+        proofs: UnboundedSender<LatencyProof>,
+    ) -> Self {
+        let mut worker =
+            Self::new(PoLRole::Prover, group, upper_bound, identity, proofs);
+        worker.verifier = Some(verifier);
+        worker
+    }
+
+    fn new(
+        role: PoLRole,
+        group: G,
+        upper_bound: u64,
+        identity: Identity,
+        proofs: UnboundedSender<LatencyProof>,
+    ) -> Self {
+        Self {
+            role,
+            group,
+            upper_bound,
+            identity,
+            calibration: None,
+            timeouts: Timeouts::default(),
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            verifier: None,
+            sessions: HashMap::new(),
+            relays: HashMap::new(),
+            proofs,
+        }
+    }
+
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Aborts a session when one of its states takes longer than its timeout
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Turns away new Provers while max_sessions sessions are running
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    /// Starts a state machine talking to peer, returning its relay address
+    async fn start_session(
+        &mut self,
+        ctx: &Context,
+        peer: Route,
+    ) -> Result<Address> {
+        let relay = Address::random(0);
+        let relay_ctx = ctx.new_context(relay.clone()).await?;
+        let (incoming, listener) = mpsc::unbounded();
+        let (sender, outgoing) = mpsc::unbounded();
+
+        let mut pol = ProofOfLatency::default()
+            .init(self.group.clone(), self.upper_bound)
+            .with_identity(self.identity.clone())
+            .with_timeouts(self.timeouts)
+            .with_streams(listener, sender);
+        if let Some(calibration) = self.calibration.as_ref() {
+            pol = pol.with_calibration(calibration.clone());
+        }
+        let session = pol
+            .start_async(self.role.clone())
+            .map_err(|_| ockam::Error::new(SESSION_ERROR, ERROR_DOMAIN))?;
+
+        let runtime = ctx.runtime();
+        let role = self.role.clone();
+        let proofs = self.proofs.clone();
+        runtime.spawn(async move {
+            match session.await {
+                // Verifiers report the proofs their Provers sign last,
+                // Provers once their Verifier closes the session
+                Ok(proof) if role == PoLRole::Verifier => {
+                    let _ = proofs.unbounded_send(proof);
+                }
+                Ok(_) => {}
+                Err(err) => debug!("Proof of Latency session failed: {}", err),
+            }
+        });
+        runtime.spawn(relay_messages(relay_ctx, ctx.address(), outgoing));
+
+        debug!("Proof of Latency session with {} started", peer);
+        self.relays.insert(peer.to_string(), relay.clone());
+        self.sessions.insert(
+            relay.clone(),
+            Session {
+                peer,
+                incoming: Some(incoming),
+                ended: false,
+                proof: None,
+            },
+        );
+        Ok(relay)
+    }
+
+    /// Relay of the session with peer, Provers only having the session
+    /// started with the worker
+    fn relay_of(&self, peer: &Route) -> Option<Address> {
+        match (self.relays.get(&peer.to_string()), &self.role) {
+            (Some(relay), _) => Some(relay.clone()),
+            (None, PoLRole::Prover) => self.sessions.keys().next().cloned(),
+            (None, PoLRole::Verifier) => None,
+        }
+    }
+
+    /// Forgets the session once both peers have closed it
+    fn remove_if_closed(&mut self, relay: &Address) {
+        let closed = match self.sessions.get(relay) {
+            Some(session) => session.ended && session.incoming.is_none(),
+            None => false,
+        };
+        if closed {
+            let session = self.sessions.remove(relay).unwrap();
+            self.relays.remove(&session.peer.to_string());
+            debug!("Proof of Latency session with {} ended", session.peer);
+        }
+    }
+
+    /// Sends a message of a state machine to its peer, an empty message once
+    /// the state machine has closed its transport
+    async fn send_frame(
+        &mut self,
+        ctx: &Context,
+        relay: Address,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let role = self.role.clone();
+        let session = match self.sessions.get_mut(&relay) {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+        if bytes.is_empty() {
+            session.ended = true;
+        } else if role == PoLRole::Prover {
+            if let Ok(SignedPoLMessage {
+                message: PoLMessage::ProofOfLatency { proof },
+                ..
+            }) = read_frame(&bytes)
+            {
+                session.proof = Some(proof);
+            }
+        }
+        let peer = session.peer.clone();
+        ctx.send(peer, bytes).await?;
+        self.remove_if_closed(&relay);
+        Ok(())
+    }
+
+    /// Hands a frame from the peer to the state machine of its session,
+    /// Verifiers starting a session for every new Prover
+    async fn receive_frame(
+        &mut self,
+        ctx: &Context,
+        peer: Route,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        if bytes.is_empty() {
+            if let Some(relay) = self.relay_of(&peer) {
+                self.close_session(&relay);
+            }
+            return Ok(());
+        }
+        let message = match read_frame(&bytes) {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Dropping a message from {}: {}", peer, err);
+                return Ok(());
+            }
+        };
+
+        let relay = match (self.relay_of(&peer), &self.role) {
+            (Some(relay), _) => relay,
+            (None, PoLRole::Verifier)
+                if self.sessions.len() >= self.max_sessions =>
+            {
+                warn!("Too many sessions, turning away {}", peer);
+                return ctx.send(peer, Vec::<u8>::new()).await;
+            }
+            (None, PoLRole::Verifier) => {
+                self.start_session(ctx, peer.clone()).await?
+            }
+            (None, PoLRole::Prover) => return Ok(()),
+        };
+        let incoming = self
+            .sessions
+            .get(&relay)
+            .and_then(|session| session.incoming.as_ref());
+        match incoming {
+            Some(incoming) if incoming.unbounded_send(message).is_ok() => {}
+            _ => debug!("The session with {} has already ended", peer),
+        }
+        Ok(())
+    }
+
+    /// The peer closed the session, the state machine receiving
+    /// PoLError::ChannelClosed after the messages before. A Prover reports
+    /// its Proof of Latency once its Verifier is done with it.
+    fn close_session(&mut self, relay: &Address) {
+        if let Some(session) = self.sessions.get_mut(relay) {
+            session.incoming = None;
+            if let Some(proof) = session.proof.take() {
+                let _ = self.proofs.unbounded_send(proof);
+            }
+        }
+        self.remove_if_closed(relay);
+    }
+}
+
+/// Sends the messages of a state machine to its worker until the state
+/// machine closes its transport, an empty message ending the session
+async fn relay_messages(
+    ctx: Context,
+    worker: Address,
+    mut outgoing: UnboundedReceiver<SignedPoLMessage>,
+) {
+    while let Some(message) = outgoing.next().await {
+        let bytes = match frame::encode(&message, MAX_FRAME_SIZE) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!("Couldn't send a Proof of Latency message: {}", err);
+                continue;
+            }
+        };
+        if ctx.send(worker.clone(), bytes).await.is_err() {
+            return;
+        }
+    }
+    let _ = ctx.send(worker, Vec::<u8>::new()).await;
+}
+
+fn read_frame(bytes: &[u8]) -> std::result::Result<SignedPoLMessage, PoLError> {
+    let mut frames = FrameBuffer::new(MAX_FRAME_SIZE);
+    frames.extend(bytes);
+    frames.next_message()?.ok_or(PoLError::Parse {
+        input: String::from("frame"),
+    })
+}
+
+#[ockam::worker]
+impl<G: VdfGroup> Worker for PoLWorker<G> {
+    type Message = Vec<u8>;
+    type Context = Context;
+
+    async fn initialize(&mut self, ctx: &mut Context) -> Result<()> {
+        if let Some(verifier) = self.verifier.clone() {
+            let relay = self.start_session(ctx, verifier).await?;
+            debug!("Prover started with relay {}", relay);
+        }
+        Ok(())
+    }
+
+    async fn handle_message(
+        &mut self,
+        ctx: &mut Context,
+        msg: Routed<Vec<u8>>,
+    ) -> Result<()> {
+        let sender = msg.sender();
+        let peer = msg.return_route();
+        let bytes = msg.body();
+
+        if self.sessions.contains_key(&sender) {
+            self.send_frame(ctx, sender, bytes).await
+        } else {
+            self.receive_frame(ctx, peer, bytes).await
+        }
+    }
+}
+
+/// Listens for Provers on the TCP address, behind a secure channel listener
+/// and the Verifier worker
+pub async fn listen<G: VdfGroup>(
+    ctx: &Context,
+    address: &str,
+    verifier: PoLWorker<G>,
+) -> Result<()> {
+    let tcp = ockam::TcpTransport::create(ctx).await?;
+    tcp.listen(address).await?;
+    let vault = Vault::create(ctx)?;
+    SecureChannel::create_listener(ctx, SECURE_CHANNEL_LISTENER, &vault)
+        .await?;
+    ctx.start_worker(VERIFIER_WORKER, verifier).await
+}
+
+/// Opens a secure channel to the Verifier node at the TCP address, returning
+/// the route to its Verifier worker
+pub async fn connect(ctx: &Context, address: &str) -> Result<Route> {
+    let tcp = ockam::TcpTransport::create(ctx).await?;
+    tcp.connect(address).await?;
+    let vault = Vault::create(ctx)?;
+    let channel = SecureChannel::create(
+        ctx,
+        route![(TCP, address), SECURE_CHANNEL_LISTENER],
+        &vault,
+    )
+    .await?;
+    Ok(route![channel.address(), VERIFIER_WORKER])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::bigint::Int;
+    use crate::vdf::group::RsaGroup;
+    use crate::RSA_2048;
+    use crossbeam::channel::unbounded;
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn verifier_sessions_are_bounded() {
+        let group = RsaGroup::new(Int::from_str(RSA_2048).unwrap());
+        let (sender, _) = mpsc::unbounded();
+        let worker = PoLWorker::verifier(group, Identity::generate(), sender);
+        assert_eq!(worker.timeouts, Timeouts::default());
+        assert!(worker.timeouts.vdf_proof_and_cap.is_some());
+        assert_eq!(worker.max_sessions, DEFAULT_MAX_SESSIONS);
+
+        let worker = worker
+            .with_timeouts(Timeouts::all(Duration::from_secs(1)))
+            .with_max_sessions(1);
+        assert_eq!(
+            worker.timeouts.generator_part,
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(worker.max_sessions, 1);
+    }
+
+    #[test]
+    fn prover_and_verifier_run_as_two_nodes() {
+        let group = RsaGroup::new(Int::from_str(RSA_2048).unwrap());
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let (listening, listener_ready) = unbounded();
+        let (results, proofs) = unbounded();
+        // The Verifier node stays up until the Prover is done
+        let (done, mut prover_done) = mpsc::unbounded();
+
+        let verifier_group = group.clone();
+        let verifier_address = address.clone();
+        let verifier_results = results.clone();
+        let verifier_node = thread::spawn(move || {
+            let (mut ctx, mut executor) = ockam::start_node();
+            executor.execute(async move {
+                let (sender, mut verified) = mpsc::unbounded();
+                let worker = PoLWorker::verifier(
+                    verifier_group,
+                    Identity::generate(),
+                    sender,
+                );
+                listen(&ctx, &verifier_address, worker).await.unwrap();
+                listening.send(()).unwrap();
+                let proof = verified.next().await;
+                verifier_results.send((PoLRole::Verifier, proof)).unwrap();
+                prover_done.next().await;
+                ctx.stop().await.unwrap();
+            })
+        });
+
+        listener_ready.recv().unwrap();
+        let prover_node = thread::spawn(move || {
+            let (mut ctx, mut executor) = ockam::start_node();
+            executor.execute(async move {
+                let (sender, mut proved) = mpsc::unbounded();
+                let verifier = connect(&ctx, &address).await.unwrap();
+                let worker = PoLWorker::prover(
+                    group,
+                    100,
+                    Identity::generate(),
+                    verifier,
+                    sender,
+                );
+                ctx.start_worker("prover", worker).await.unwrap();
+                let proof = proved.next().await;
+                results.send((PoLRole::Prover, proof)).unwrap();
+                done.unbounded_send(()).unwrap();
+                ctx.stop().await.unwrap();
+            })
+        });
+        assert!(prover_node.join().unwrap().is_ok());
+        assert!(verifier_node.join().unwrap().is_ok());
+
+        // Both nodes end up with the same Proof of Latency
+        let proofs: Vec<_> = proofs.try_iter().collect();
+        assert_eq!(proofs.len(), 2);
+        let proof = proofs[0].1.clone().unwrap();
+        assert_eq!(proofs[1].1, Some(proof.clone()));
+        assert!(proof
+            .verify(&RsaGroup::new(Int::from_str(RSA_2048).unwrap()))
+            .is_ok());
+    }
+}
@@ -0,0 +1,428 @@
+//! Persistent store of finished Proofs of Latency in sled, so that a node
+//! still knows the latencies it has proven after a restart. Proofs are keyed
+//! by the pair of peers and the time they were stored, with secondary
+//! indexes by peer and by time pointing to the primary keys.
+use crate::archive::{decode, encode};
+use crate::error::PoLError;
+use crate::latency::LatencyProof;
+use crate::vdf::group::VdfGroup;
+use bytecheck::CheckBytes;
+use ed25519_dalek::PUBLIC_KEY_LENGTH;
+use rkyv::{Archive, Deserialize, Serialize};
+use sled::transaction::{TransactionError, Transactional};
+use std::error::Error;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of the timestamp and the unique id ending every key
+const SUFFIX_LENGTH: usize = 16;
+
+/// A verified Proof of Latency as stored
+#[derive(Archive, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[archive(derive(CheckBytes))]
+pub struct StoredProof {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// The latency bound in iterations returned by LatencyProof::verify
+    pub latency: u64,
+    pub proof: LatencyProof,
+}
+
+/// Which proofs ProofStore::prune removes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrunePolicy {
+    /// Removes proofs stored longer ago than this
+    pub max_age: Option<Duration>,
+    /// Keeps only this many of the latest proofs of each peer pair
+    pub keep_per_pair: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// The proof didn't verify, so it wasn't stored
+    InvalidProof(PoLError),
+    Database(sled::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::InvalidProof(err) => {
+                write!(f, "Refusing to store an invalid proof: {}", err)
+            }
+            StoreError::Database(err) => write!(f, "Proof store: {}", err),
+        }
+    }
+}
+
+impl Error for StoreError {}
+
+impl From<sled::Error> for StoreError {
+    fn from(err: sled::Error) -> Self {
+        StoreError::Database(err)
+    }
+}
+
+/// Milliseconds since the Unix epoch
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Finished Proofs of Latency in sled. The primary key is the two public keys
+/// in ascending order followed by the timestamp and a unique id, so the
+/// proofs of a pair are adjacent and in chronological order.
+#[derive(Debug, Clone)]
+pub struct ProofStore {
+    db: sled::Db,
+    proofs: sled::Tree,
+    // Public key, timestamp and id of every proof the key is a part of
+    by_peer: sled::Tree,
+    // Timestamp and id of every proof
+    by_time: sled::Tree,
+}
+
+impl ProofStore {
+    /// Opens the store from the sled database at path
+    pub fn open<P: AsRef<Path>>(path: P) -> sled::Result<Self> {
+        Self::from_db(&sled::open(path)?)
+    }
+
+    /// Opens the store from an already open database
+    pub fn from_db(db: &sled::Db) -> sled::Result<Self> {
+        Ok(Self {
+            db: db.clone(),
+            proofs: db.open_tree("proofs")?,
+            by_peer: db.open_tree("proofs/by_peer")?,
+            by_time: db.open_tree("proofs/by_time")?,
+        })
+    }
+
+    /// Verifies the proof in group and stores it with the current time
+    pub fn insert<G: VdfGroup>(
+        &self,
+        group: &G,
+        proof: LatencyProof,
+    ) -> Result<StoredProof, StoreError> {
+        self.insert_at(group, proof, unix_millis())
+    }
+
+    /// Verifies the proof in group and stores it with the timestamp
+    pub fn insert_at<G: VdfGroup>(
+        &self,
+        group: &G,
+        proof: LatencyProof,
+        timestamp: u64,
+    ) -> Result<StoredProof, StoreError> {
+        let latency = proof.verify(group).map_err(StoreError::InvalidProof)?;
+        let stored = StoredProof {
+            timestamp,
+            latency,
+            proof,
+        };
+        self.put(&stored)?;
+        Ok(stored)
+    }
+
+    fn put(&self, stored: &StoredProof) -> sled::Result<()> {
+        let mut suffix = [0u8; SUFFIX_LENGTH];
+        suffix[..8].copy_from_slice(&stored.timestamp.to_be_bytes());
+        suffix[8..].copy_from_slice(&self.db.generate_id()?.to_be_bytes());
+        let (first, second) =
+            ordered(&stored.proof.prover_key, &stored.proof.verifier_key);
+        let key = [first, second, &suffix].concat();
+        let value = encode(stored);
+
+        (&self.proofs, &self.by_peer, &self.by_time)
+            .transaction(|(proofs, by_peer, by_time)| {
+                proofs.insert(key.as_slice(), value.as_slice())?;
+                by_peer.insert([first, &suffix].concat(), key.as_slice())?;
+                by_peer.insert([second, &suffix].concat(), key.as_slice())?;
+                by_time.insert(&suffix[..], key.as_slice())?;
+                Ok(())
+            })
+            .map_err(storage_error)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Removes the proof stored under the primary key from every tree
+    fn remove(&self, key: &[u8]) -> sled::Result<()> {
+        let suffix = &key[2 * PUBLIC_KEY_LENGTH..];
+        let first = &key[..PUBLIC_KEY_LENGTH];
+        let second = &key[PUBLIC_KEY_LENGTH..2 * PUBLIC_KEY_LENGTH];
+        (&self.proofs, &self.by_peer, &self.by_time)
+            .transaction(|(proofs, by_peer, by_time)| {
+                proofs.remove(key)?;
+                by_peer.remove([first, suffix].concat())?;
+                by_peer.remove([second, suffix].concat())?;
+                by_time.remove(suffix)?;
+                Ok(())
+            })
+            .map_err(storage_error)
+    }
+
+    fn get(&self, key: &[u8]) -> sled::Result<Option<StoredProof>> {
+        Ok(self.proofs.get(key)?.and_then(|value| decode(&value)))
+    }
+
+    /// Follows the primary keys in an index to the proofs
+    fn resolve(
+        &self,
+        index: impl Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>>,
+    ) -> sled::Result<Vec<StoredProof>> {
+        let mut proofs = Vec::new();
+        for entry in index {
+            let (_, key) = entry?;
+            if let Some(stored) = self.get(&key)? {
+                proofs.push(stored);
+            }
+        }
+        Ok(proofs)
+    }
+
+    /// The latest proof between the two peers, in either role
+    pub fn latest_between(
+        &self,
+        peer: &[u8],
+        other: &[u8],
+    ) -> sled::Result<Option<StoredProof>> {
+        let (first, second) = ordered(peer, other);
+        for entry in self.proofs.scan_prefix([first, second].concat()).rev() {
+            let (key, value) = entry?;
+            if key.len() == 2 * PUBLIC_KEY_LENGTH + SUFFIX_LENGTH {
+                if let Some(stored) = decode(&value) {
+                    return Ok(Some(stored));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The latest proof the peer is a part of
+    pub fn latest_for_peer(
+        &self,
+        peer: &[u8],
+    ) -> sled::Result<Option<StoredProof>> {
+        let latest = self.by_peer.scan_prefix(peer).rev().take(1);
+        Ok(self.resolve(latest)?.pop())
+    }
+
+    /// Every proof the peer is a part of, oldest first
+    pub fn for_peer(&self, peer: &[u8]) -> sled::Result<Vec<StoredProof>> {
+        self.resolve(self.by_peer.scan_prefix(peer))
+    }
+
+    /// The proofs stored within the range of timestamps, oldest first
+    pub fn between<R: RangeBounds<u64>>(
+        &self,
+        timestamps: R,
+    ) -> sled::Result<Vec<StoredProof>> {
+        let start = match timestamps.start_bound() {
+            Bound::Included(start) => Bound::Included(time_key(*start, 0)),
+            Bound::Excluded(start) => Bound::Excluded(time_key(*start, !0)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match timestamps.end_bound() {
+            Bound::Included(end) => Bound::Included(time_key(*end, !0)),
+            Bound::Excluded(end) => Bound::Excluded(time_key(*end, 0)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.resolve(self.by_time.range((start, end)))
+    }
+
+    /// Removes the proofs the policy doesn't keep at the time now, returning
+    /// how many were removed
+    pub fn prune(&self, policy: &PrunePolicy, now: u64) -> sled::Result<usize> {
+        let mut removed = Vec::new();
+        if let Some(max_age) = policy.max_age {
+            let cutoff = now.saturating_sub(max_age.as_millis() as u64);
+            for entry in self.by_time.range(..time_key(cutoff, 0)) {
+                removed.push(entry?.1);
+            }
+        }
+        if let Some(keep) = policy.keep_per_pair {
+            let mut pair = Vec::new();
+            let mut kept = 0;
+            for key in self.proofs.iter().keys().rev() {
+                let key = key?;
+                if key.len() < 2 * PUBLIC_KEY_LENGTH {
+                    continue;
+                }
+                if key[..2 * PUBLIC_KEY_LENGTH] != pair[..] {
+                    pair = key[..2 * PUBLIC_KEY_LENGTH].to_vec();
+                    kept = 0;
+                }
+                if kept < keep {
+                    kept += 1;
+                } else {
+                    removed.push(key);
+                }
+            }
+        }
+
+        removed.sort();
+        removed.dedup();
+        for key in removed.iter() {
+            self.remove(key)?;
+        }
+        self.db.flush()?;
+        Ok(removed.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+}
+
+/// The keys of a pair in ascending order, the same for both roles
+fn ordered<'a>(a: &'a [u8], b: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn time_key(timestamp: u64, id: u64) -> Vec<u8> {
+    [timestamp.to_be_bytes(), id.to_be_bytes()].concat()
+}
+
+fn storage_error(err: TransactionError<sled::Error>) -> sled::Error {
+    match err {
+        TransactionError::Abort(err) | TransactionError::Storage(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::latency::made_up_proof;
+    use crate::vdf::bigint::Int;
+    use crate::vdf::group::RsaGroup;
+    use crate::{PoLRole, ProofOfLatency, RSA_2048};
+    use futures::channel::mpsc;
+    use std::str::FromStr;
+
+    fn store() -> ProofStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        ProofStore::from_db(&db).unwrap()
+    }
+
+    fn stored(prover: u8, verifier: u8, timestamp: u64) -> StoredProof {
+        StoredProof {
+            timestamp,
+            latency: timestamp * 10,
+            proof: made_up_proof(prover, verifier),
+        }
+    }
+
+    #[test]
+    fn only_verified_proofs_are_stored() {
+        let group = RsaGroup::new(Int::from_str(RSA_2048).unwrap());
+        let (to_verifier, verifier_incoming) = mpsc::unbounded();
+        let (to_prover, prover_incoming) = mpsc::unbounded();
+        let prover = ProofOfLatency::default()
+            .init(group.clone(), 100)
+            .with_streams(prover_incoming, to_verifier)
+            .start_async(PoLRole::Prover)
+            .unwrap();
+        let verifier = ProofOfLatency::default()
+            .init(group.clone(), u64::MAX)
+            .with_streams(verifier_incoming, to_prover)
+            .start_async(PoLRole::Verifier)
+            .unwrap();
+        let (proof, _) =
+            async_std::task::block_on(futures::future::join(prover, verifier));
+        let proof = proof.unwrap();
+
+        let store = store();
+        let mut forged = proof.clone();
+        forged.prover_signature = forged.verifier_signature.clone();
+        assert!(matches!(
+            store.insert(&group, forged),
+            Err(StoreError::InvalidProof(PoLError::InvalidSignature))
+        ));
+        assert!(store.is_empty());
+
+        let stored = store.insert_at(&group, proof.clone(), 7).unwrap();
+        assert_eq!(stored.latency, proof.verify(&group).unwrap());
+        assert_eq!(
+            store.latest_for_peer(&proof.verifier_key).unwrap(),
+            Some(stored)
+        );
+    }
+
+    #[test]
+    fn proofs_are_found_by_peer_and_time() {
+        let store = store();
+        for stored in
+            [stored(1, 2, 10), stored(3, 1, 20), stored(2, 1, 30)].iter()
+        {
+            store.put(stored).unwrap();
+        }
+
+        let peer = |byte| vec![byte; PUBLIC_KEY_LENGTH];
+        let latest = store.latest_between(&peer(1), &peer(2)).unwrap();
+        assert_eq!(latest, Some(stored(2, 1, 30)));
+        assert_eq!(
+            store.latest_for_peer(&peer(3)).unwrap(),
+            Some(stored(3, 1, 20))
+        );
+        assert_eq!(store.latest_for_peer(&peer(4)).unwrap(), None);
+        let timestamps = |proofs: Vec<StoredProof>| {
+            proofs.iter().map(|s| s.timestamp).collect::<Vec<_>>()
+        };
+        assert_eq!(timestamps(store.for_peer(&peer(1)).unwrap()), [10, 20, 30]);
+        assert_eq!(timestamps(store.for_peer(&peer(2)).unwrap()), [10, 30]);
+        assert_eq!(timestamps(store.between(10..30).unwrap()), [10, 20]);
+        assert_eq!(timestamps(store.between(20..=30).unwrap()), [20, 30]);
+        assert_eq!(timestamps(store.between(..).unwrap()), [10, 20, 30]);
+    }
+
+    #[test]
+    fn pruning_removes_old_and_surplus_proofs() {
+        let store = store();
+        for stored in [
+            stored(1, 2, 10),
+            stored(1, 2, 20),
+            stored(2, 1, 30),
+            stored(1, 3, 15),
+            stored(1, 3, 40),
+        ]
+        .iter()
+        {
+            store.put(stored).unwrap();
+        }
+
+        let by_age = PrunePolicy {
+            max_age: Some(Duration::from_millis(30)),
+            ..PrunePolicy::default()
+        };
+        assert_eq!(store.prune(&by_age, 50).unwrap(), 2);
+        assert_eq!(store.len(), 3);
+
+        let by_count = PrunePolicy {
+            keep_per_pair: Some(1),
+            ..PrunePolicy::default()
+        };
+        assert_eq!(store.prune(&by_count, 50).unwrap(), 1);
+        let peer = vec![1; PUBLIC_KEY_LENGTH];
+        let timestamps: Vec<u64> = store
+            .for_peer(&peer)
+            .unwrap()
+            .iter()
+            .map(|s| s.timestamp)
+            .collect();
+        assert_eq!(timestamps, [30, 40]);
+        assert_eq!(store.between(..).unwrap().len(), 2);
+    }
+}
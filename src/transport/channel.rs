@@ -0,0 +1,72 @@
+use crate::error::PoLError;
+use crate::identity::SignedPoLMessage;
+use crate::transport::Transport;
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// In-memory transport between two peers in the same process
+#[derive(Debug)]
+pub struct ChannelTransport {
+    sender: Option<Sender<SignedPoLMessage>>,
+    receiver: Receiver<SignedPoLMessage>,
+}
+
+impl ChannelTransport {
+    pub fn new(
+        sender: Sender<SignedPoLMessage>,
+        receiver: Receiver<SignedPoLMessage>,
+    ) -> Self {
+        Self {
+            sender: Some(sender),
+            receiver,
+        }
+    }
+
+    /// Two transports connected to each other
+    pub fn pair() -> (Self, Self) {
+        let (a_sender, b_receiver) = unbounded();
+        let (b_sender, a_receiver) = unbounded();
+        (
+            Self::new(a_sender, a_receiver),
+            Self::new(b_sender, b_receiver),
+        )
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, message: SignedPoLMessage) -> Result<(), PoLError> {
+        self.sender
+            .as_ref()
+            .ok_or(PoLError::ChannelClosed)?
+            .send(message)
+            .map_err(|_| PoLError::ChannelClosed)
+    }
+
+    fn recv(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<SignedPoLMessage, PoLError> {
+        match deadline {
+            Some(deadline) => {
+                self.receiver
+                    .recv_deadline(deadline)
+                    .map_err(|err| match err {
+                        RecvTimeoutError::Timeout => PoLError::Timeout,
+                        RecvTimeoutError::Disconnected => {
+                            PoLError::ChannelClosed
+                        }
+                    })
+            }
+            None => self.receiver.recv().map_err(|_| PoLError::ChannelClosed),
+        }
+    }
+
+    fn close(&mut self) {
+        self.sender = None;
+    }
+
+    fn peer_address(&self) -> Option<SocketAddr> {
+        None
+    }
+}
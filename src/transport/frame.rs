@@ -0,0 +1,191 @@
+//! Framing of SignedPoLMessages on byte streams. A frame is the rkyv archive
+//! of a message prefixed with its length as a big-endian u32. Frames larger
+//! than the maximum size are refused before they are buffered, and archives
+//! are validated before any of their fields are read.
+use crate::archive;
+use crate::error::PoLError;
+use crate::identity::SignedPoLMessage;
+
+/// Length of the length prefix of every frame
+pub const LENGTH_BYTES: usize = 4;
+
+/// Default maximum size of a frame without its length prefix. A Proof of
+/// Latency in a 2048-bit group takes a few kilobytes.
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// Archives the message into a frame
+pub fn encode(
+    message: &SignedPoLMessage,
+    max_frame_size: usize,
+) -> Result<Vec<u8>, PoLError> {
+    let archive = archive::encode(message);
+    check_size(archive.len(), max_frame_size)?;
+
+    let mut frame = Vec::with_capacity(LENGTH_BYTES + archive.len());
+    frame.extend_from_slice(&(archive.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&archive);
+    Ok(frame)
+}
+
+/// Checks that the archive is valid before reading the message from it
+pub fn decode(archive: &[u8]) -> Result<SignedPoLMessage, PoLError> {
+    archive::decode(archive).ok_or(PoLError::Parse {
+        input: String::from("frame"),
+    })
+}
+
+fn check_size(size: usize, max_frame_size: usize) -> Result<(), PoLError> {
+    if size > max_frame_size || size > u32::MAX as usize {
+        Err(PoLError::FrameTooLarge {
+            size: size as u64,
+            max: max_frame_size as u64,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Collects bytes read from a stream into frames
+#[derive(Debug)]
+pub struct FrameBuffer {
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+}
+
+impl FrameBuffer {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_frame_size,
+        }
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Takes the first message out of the buffer if its frame has been
+    /// received whole. The length prefix is checked as soon as it arrives,
+    /// an oversized frame leaving the stream unusable.
+    pub fn next_message(
+        &mut self,
+    ) -> Result<Option<SignedPoLMessage>, PoLError> {
+        if self.buffer.len() < LENGTH_BYTES {
+            return Ok(None);
+        }
+        let mut length = [0u8; LENGTH_BYTES];
+        length.copy_from_slice(&self.buffer[..LENGTH_BYTES]);
+        let length = u32::from_be_bytes(length) as usize;
+        check_size(length, self.max_frame_size)?;
+
+        let end = LENGTH_BYTES + length;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+        let message = decode(&self.buffer[LENGTH_BYTES..end]);
+        self.buffer.drain(..end);
+        message.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::identity::Identity;
+    use crate::PoLMessage;
+
+    fn message() -> SignedPoLMessage {
+        SignedPoLMessage::new(
+            PoLMessage::Cap {
+                num: String::from("7"),
+            },
+            &Identity::generate(),
+        )
+    }
+
+    #[test]
+    fn frames_are_read_byte_by_byte() {
+        let message = message();
+        let frame = encode(&message, MAX_FRAME_SIZE).unwrap();
+        let mut buffer = FrameBuffer::new(MAX_FRAME_SIZE);
+        let mut received = Vec::new();
+        for byte in frame.iter().chain(frame.iter()) {
+            assert_eq!(buffer.next_message(), Ok(None));
+            buffer.extend(&[*byte]);
+            if let Some(message) = buffer.next_message().unwrap() {
+                received.push(message);
+            }
+        }
+        assert_eq!(received, vec![message.clone(), message]);
+        assert_eq!(buffer.next_message(), Ok(None));
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        let message = message();
+        let size =
+            encode(&message, MAX_FRAME_SIZE).unwrap().len() - LENGTH_BYTES;
+        assert_eq!(
+            encode(&message, size - 1),
+            Err(PoLError::FrameTooLarge {
+                size: size as u64,
+                max: size as u64 - 1
+            })
+        );
+
+        // Only the length prefix is needed to refuse a frame
+        let mut buffer = FrameBuffer::new(MAX_FRAME_SIZE);
+        buffer.extend(&(MAX_FRAME_SIZE as u32 + 1).to_be_bytes());
+        assert!(matches!(
+            buffer.next_message(),
+            Err(PoLError::FrameTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_archives_are_refused() {
+        let mut frame = encode(&message(), MAX_FRAME_SIZE).unwrap();
+        // Point the relative pointers of the archive somewhere else
+        let last = frame.len() - 1;
+        frame[last - 16..=last].iter_mut().for_each(|b| *b = 0xff);
+        let mut buffer = FrameBuffer::new(MAX_FRAME_SIZE);
+        buffer.extend(&frame);
+        assert!(matches!(buffer.next_message(), Err(PoLError::Parse { .. })));
+        assert!(decode(&[0u8; 3]).is_err());
+    }
+
+    #[test]
+    fn relayed_aborts_survive_the_frame_flat() {
+        let mut error = PoLError::Parse {
+            input: String::from("7"),
+        };
+        for _ in 0..100_000 {
+            error = PoLError::aborted_by_peer(error);
+        }
+        assert_eq!(error.kind(), ErrorKind::PeerAborted);
+        let message = SignedPoLMessage::new(
+            PoLMessage::Abort { error },
+            &Identity::generate(),
+        );
+        let frame = encode(&message, MAX_FRAME_SIZE).unwrap();
+        assert!(frame.len() < 1024);
+        assert_eq!(decode(&frame[LENGTH_BYTES..]), Ok(message));
+    }
+
+    #[test]
+    fn deeply_nested_archives_are_refused() {
+        // Aborts as a recursive error would archive them, every one pointing
+        // to the one before it, filling the whole frame
+        let mut archive = Vec::with_capacity(MAX_FRAME_SIZE);
+        while archive.len() < MAX_FRAME_SIZE {
+            archive.push(ErrorKind::PeerAborted as u8);
+            archive.extend_from_slice(&[0; 3]);
+            archive.extend_from_slice(&(-12i32).to_le_bytes());
+        }
+        let mut buffer = FrameBuffer::new(MAX_FRAME_SIZE);
+        buffer.extend(&(archive.len() as u32).to_be_bytes());
+        buffer.extend(&archive);
+        assert!(matches!(buffer.next_message(), Err(PoLError::Parse { .. })));
+    }
+}
@@ -0,0 +1,119 @@
+//! Transports carry SignedPoLMessages between the peers. The state machine
+//! only talks to the other peer through the Transport it was given, so the
+//! same protocol runs over in-memory channels and over a network.
+use crate::error::PoLError;
+use crate::identity::SignedPoLMessage;
+use futures::sink::{Sink, SinkExt};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use std::fmt::{self, Debug};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::Instant;
+
+pub mod channel;
+pub mod frame;
+pub mod tcp;
+
+pub use channel::ChannelTransport;
+pub use tcp::TcpTransport;
+
+/// A connection to the other peer
+pub trait Transport: Debug + Send {
+    fn send(&mut self, message: SignedPoLMessage) -> Result<(), PoLError>;
+
+    /// Waits for the next message until the deadline, or forever without one.
+    /// Returns PoLError::Timeout when the deadline passes and
+    /// PoLError::ChannelClosed when the peer is gone.
+    fn recv(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<SignedPoLMessage, PoLError>;
+
+    /// Closes the connection, the peer's recv returning
+    /// PoLError::ChannelClosed
+    fn close(&mut self);
+
+    /// Network address of the peer, if it has one
+    fn peer_address(&self) -> Option<SocketAddr>;
+}
+
+/// How a state machine reaches the other peer: a blocking Transport, or
+/// async message streams that don't block the executor
+pub(crate) enum Connection {
+    Blocking(Box<dyn Transport>),
+    Streams {
+        incoming: BoxStream<'static, SignedPoLMessage>,
+        outgoing: Pin<Box<dyn Sink<SignedPoLMessage, Error = PoLError> + Send>>,
+    },
+}
+
+impl Connection {
+    pub(crate) fn streams<S, K>(incoming: S, outgoing: K) -> Self
+    where
+        S: Stream<Item = SignedPoLMessage> + Send + 'static,
+        K: Sink<SignedPoLMessage> + Send + 'static,
+    {
+        Connection::Streams {
+            incoming: incoming.boxed(),
+            outgoing: Box::pin(
+                outgoing.sink_map_err(|_| PoLError::ChannelClosed),
+            ),
+        }
+    }
+
+    pub(crate) async fn send(
+        &mut self,
+        message: SignedPoLMessage,
+    ) -> Result<(), PoLError> {
+        match self {
+            Connection::Blocking(transport) => transport.send(message),
+            Connection::Streams { outgoing, .. } => {
+                outgoing.send(message).await
+            }
+        }
+    }
+
+    /// Waits for the next message until the deadline, or forever without one
+    pub(crate) async fn recv(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<SignedPoLMessage, PoLError> {
+        let incoming = match self {
+            Connection::Blocking(transport) => return transport.recv(deadline),
+            Connection::Streams { incoming, .. } => incoming,
+        };
+        let message = match deadline {
+            Some(deadline) => {
+                let timeout =
+                    deadline.saturating_duration_since(Instant::now());
+                async_std::future::timeout(timeout, incoming.next())
+                    .await
+                    .map_err(|_| PoLError::Timeout)?
+            }
+            None => incoming.next().await,
+        };
+        message.ok_or(PoLError::ChannelClosed)
+    }
+
+    pub(crate) async fn close(&mut self) {
+        match self {
+            Connection::Blocking(transport) => transport.close(),
+            Connection::Streams { outgoing, .. } => {
+                if outgoing.close().await.is_err() {
+                    debug!("Message stream was already closed");
+                }
+            }
+        }
+    }
+}
+
+impl Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Connection::Blocking(transport) => {
+                f.debug_tuple("Blocking").field(transport).finish()
+            }
+            Connection::Streams { .. } => f.write_str("Streams"),
+        }
+    }
+}
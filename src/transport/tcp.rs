@@ -0,0 +1,242 @@
+use crate::error::PoLError;
+use crate::identity::SignedPoLMessage;
+use crate::transport::frame::{self, FrameBuffer, MAX_FRAME_SIZE};
+use crate::transport::Transport;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Instant;
+
+/// TCP transport sending each message in a frame::encode frame
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+    // Bytes received but not yet parsed into a message
+    frames: FrameBuffer,
+    max_frame_size: usize,
+}
+
+impl TcpTransport {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        Self::from_stream(TcpStream::connect(address)?)
+    }
+
+    /// Waits for a peer to connect to listener
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        Self::from_stream(stream)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        // Messages are small and latency is what is being measured
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            frames: FrameBuffer::new(MAX_FRAME_SIZE),
+            max_frame_size: MAX_FRAME_SIZE,
+        })
+    }
+
+    /// Refuses to send or receive frames larger than max_frame_size bytes,
+    /// frame::MAX_FRAME_SIZE by default
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.frames = FrameBuffer::new(max_frame_size);
+        self.max_frame_size = max_frame_size;
+        self
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: SignedPoLMessage) -> Result<(), PoLError> {
+        let frame = frame::encode(&message, self.max_frame_size)?;
+        self.stream
+            .write_all(&frame)
+            .map_err(|_| PoLError::ChannelClosed)
+    }
+
+    fn recv(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<SignedPoLMessage, PoLError> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.frames.next_message() {
+                Ok(Some(message)) => return Ok(message),
+                Ok(None) => {}
+                Err(PoLError::FrameTooLarge { size, max }) => {
+                    // The rest of the stream can't be framed anymore
+                    self.close();
+                    return Err(PoLError::FrameTooLarge { size, max });
+                }
+                Err(err) => return Err(err),
+            }
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        return Err(PoLError::Timeout);
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            self.stream
+                .set_read_timeout(timeout)
+                .map_err(|_| PoLError::ChannelClosed)?;
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(PoLError::ChannelClosed),
+                Ok(read) => self.frames.extend(&chunk[..read]),
+                Err(err)
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Err(PoLError::Timeout)
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return Err(PoLError::ChannelClosed),
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.frames = FrameBuffer::new(self.max_frame_size);
+        if self.stream.shutdown(Shutdown::Both).is_err() {
+            debug!("TCP connection was already closed");
+        }
+    }
+
+    fn peer_address(&self) -> Option<SocketAddr> {
+        self.stream.peer_addr().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Identity;
+    use crate::vdf::bigint::Int;
+    use crate::vdf::group::RsaGroup;
+    use crate::{PoLMessage, PoLRole, ProofOfLatency, RSA_2048};
+    use crossbeam::channel::{unbounded, Sender};
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::Duration;
+
+    fn connected() -> (TcpTransport, TcpTransport) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || TcpTransport::accept(&listener));
+        let client = TcpTransport::connect(address).unwrap();
+        (client, server.join().unwrap().unwrap())
+    }
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        let (mut client, mut server) = connected();
+        let message = SignedPoLMessage::new(
+            PoLMessage::GeneratorPartAndCap {
+                generator_part: String::from("12"),
+                cap: String::from("7"),
+            },
+            &Identity::generate(),
+        );
+
+        assert!(client.send(message.clone()).is_ok());
+        assert!(client.send(message.clone()).is_ok());
+        assert_eq!(server.recv(None), Ok(message.clone()));
+        assert_eq!(server.recv(None), Ok(message));
+        assert_eq!(
+            server.peer_address(),
+            Some(client.stream.local_addr().unwrap())
+        );
+    }
+
+    #[test]
+    fn recv_times_out_and_notices_a_closed_connection() {
+        let (mut client, mut server) = connected();
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert_eq!(server.recv(Some(deadline)), Err(PoLError::Timeout));
+
+        client.close();
+        assert_eq!(server.recv(None), Err(PoLError::ChannelClosed));
+    }
+
+    #[test]
+    fn garbage_is_not_a_message() {
+        let (mut client, mut server) = connected();
+        let mut garbage = 8u32.to_be_bytes().to_vec();
+        garbage.extend_from_slice(&[0xff; 8]);
+        client.stream.write_all(&garbage).unwrap();
+        assert!(matches!(server.recv(None), Err(PoLError::Parse { .. })));
+    }
+
+    #[test]
+    fn oversized_frame_closes_the_connection() {
+        let (mut client, server) = connected();
+        let mut server = server.with_max_frame_size(16);
+        client.stream.write_all(&(17u32).to_be_bytes()).unwrap();
+        assert_eq!(
+            server.recv(None),
+            Err(PoLError::FrameTooLarge { size: 17, max: 16 })
+        );
+        assert_eq!(server.recv(None), Err(PoLError::ChannelClosed));
+    }
+
+    /// Copies the messages sent through the transport
+    #[derive(Debug)]
+    struct Recording {
+        inner: TcpTransport,
+        sent: Sender<SignedPoLMessage>,
+    }
+
+    impl Transport for Recording {
+        fn send(&mut self, message: SignedPoLMessage) -> Result<(), PoLError> {
+            let _ = self.sent.send(message.clone());
+            self.inner.send(message)
+        }
+
+        fn recv(
+            &mut self,
+            deadline: Option<Instant>,
+        ) -> Result<SignedPoLMessage, PoLError> {
+            self.inner.recv(deadline)
+        }
+
+        fn close(&mut self) {
+            self.inner.close()
+        }
+
+        fn peer_address(&self) -> Option<SocketAddr> {
+            self.inner.peer_address()
+        }
+    }
+
+    #[test]
+    fn prover_and_verifier_run_over_loopback() {
+        let modulus = Int::from_str(RSA_2048).unwrap();
+        let (prover_transport, verifier_transport) = connected();
+        let (sent, sent_by_prover) = unbounded();
+
+        let verifier = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus.clone()), u64::MAX)
+            .with_transport(verifier_transport);
+        let prover = ProofOfLatency::default()
+            .init(RsaGroup::new(modulus), 100)
+            .with_transport(Recording {
+                inner: prover_transport,
+                sent,
+            });
+        let verifier = verifier.start(PoLRole::Verifier).unwrap();
+        let prover = prover.start(PoLRole::Prover).unwrap();
+
+        // The prover's last message is the Proof of Latency signed by both
+        match sent_by_prover.iter().last().map(|signed| signed.message) {
+            Some(PoLMessage::ProofOfLatency { proof }) => {
+                let group = RsaGroup::new(Int::from_str(RSA_2048).unwrap());
+                assert!(proof.verify(&group).is_ok());
+                assert_eq!(prover.join().unwrap(), Ok(proof.clone()));
+                assert_eq!(verifier.join().unwrap(), Ok(proof));
+            }
+            other => panic!("Expected a Proof of Latency, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Batch verification of Wesolowski proofs. Instead of checking
+//! y = pi^l * g^r for each proof, proofs in the same group are checked at once
+//! with random small exponents rho, prod(y^rho) = prod(pi^(l*rho) * g^(r*rho)),
+//! with multi-exponentiations that share their squarings between proofs. A
+//! failing batch is split in half until the failing proofs are found.
+//!
+//! Like a single Wesolowski proof in an RSA group, a batch is only sound up to
+//! the sign of the output, as -1 is an element of known order.
+use crate::vdf::bigint::{Generator, Int};
+use crate::vdf::group::VdfGroup;
+use crate::vdf::proof::{DeserializableVDFProof, ProofType, VDFProof};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Proofs in the same group are verified in batches of at most this many
+/// proofs in parallel
+pub const MAX_BATCH_SIZE: usize = 256;
+
+/// Bit length of the random exponents the proofs are combined with
+pub const BATCH_EXPONENT_BITS: usize = 64;
+
+/// Verifies the proofs, returning the indices of the ones that failed in
+/// ascending order. Proofs are batched by their group and the batches are
+/// verified in parallel. Proofs that are alone in their group, malformed or
+/// of another proof type than Wesolowski are verified one by one.
+pub fn verify_batch<G: VdfGroup>(
+    proofs: &[DeserializableVDFProof],
+) -> Vec<usize> {
+    let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, proof) in proofs.iter().enumerate() {
+        groups.entry(&proof.group).or_default().push(index);
+    }
+
+    let mut failed: Vec<usize> = groups
+        .into_par_iter()
+        .flat_map(|(_, indices)| verify_same_group::<G>(proofs, indices))
+        .collect();
+    failed.sort_unstable();
+    failed
+}
+
+/// Bases and exponents of a multi-exponentiation
+type Terms<'a, E> = Vec<(&'a E, Int)>;
+
+/// A decoded Wesolowski proof with its generator exponent r = 2^T mod l
+struct Candidate<G: VdfGroup> {
+    index: usize,
+    proof: VDFProof<G>,
+    r: Int,
+}
+
+fn verify_same_group<G: VdfGroup>(
+    proofs: &[DeserializableVDFProof],
+    indices: Vec<usize>,
+) -> Vec<usize> {
+    let mut failed = Vec::new();
+    let mut candidates = Vec::new();
+
+    for index in indices {
+        let proof = match proofs[index].serialize::<G>() {
+            Ok(proof) => proof,
+            Err(_) => {
+                failed.push(index);
+                continue;
+            }
+        };
+        if proof.proof_type == ProofType::Pietrzak {
+            if !proof.verify() {
+                failed.push(index);
+            }
+            continue;
+        }
+        match proof.wesolowski_exponent() {
+            Some(r) => candidates.push(Candidate { index, proof, r }),
+            None => failed.push(index),
+        }
+    }
+
+    failed.par_extend(
+        candidates
+            .par_chunks(MAX_BATCH_SIZE)
+            .flat_map(failing_candidates),
+    );
+    failed
+}
+
+/// Bisects the candidates until the failing ones are found
+fn failing_candidates<G: VdfGroup>(candidates: &[Candidate<G>]) -> Vec<usize> {
+    match candidates.len() {
+        0 => Vec::new(),
+        1 => {
+            let candidate = &candidates[0];
+            if candidate.proof.verify() {
+                Vec::new()
+            } else {
+                vec![candidate.index]
+            }
+        }
+        _ => {
+            if batch_holds(candidates) {
+                return Vec::new();
+            }
+            let (left, right) = candidates.split_at(candidates.len() / 2);
+            let mut failed = failing_candidates(left);
+            failed.extend(failing_candidates(right));
+            failed
+        }
+    }
+}
+
+/// Checks the combined equation of the candidates with fresh random exponents.
+/// Proofs sharing a cap, or an iteration count and thus r, are combined
+/// before raising to the cap or r, so those exponentiations are done only
+/// once.
+fn batch_holds<G: VdfGroup>(candidates: &[Candidate<G>]) -> bool {
+    let group = &candidates[0].proof.group;
+    let mut outputs = Vec::with_capacity(candidates.len());
+    let mut by_cap: HashMap<String, (&Int, Terms<G::Element>)> = HashMap::new();
+    let mut by_r: HashMap<String, (&Int, Terms<G::Element>)> = HashMap::new();
+
+    for candidate in candidates {
+        let rho = Generator::new_uint(BATCH_EXPONENT_BITS);
+        let proof = &candidate.proof;
+        by_cap
+            .entry(proof.cap.to_str_radix(16, false))
+            .or_insert((&proof.cap, Vec::new()))
+            .1
+            .push((&proof.pi, rho.clone()));
+        by_r.entry(candidate.r.to_str_radix(16, false))
+            .or_insert((&candidate.r, Vec::new()))
+            .1
+            .push((&proof.generator, rho.clone()));
+        outputs.push((&proof.output.result, rho));
+    }
+
+    let combined: Vec<G::Element> = by_cap
+        .values()
+        .chain(by_r.values())
+        .map(|(exponent, terms)| {
+            group.exponentiate(&multi_exponentiate(group, terms), exponent)
+        })
+        .collect();
+    let proofs = combined
+        .iter()
+        .fold(group.identity(), |acc, x| group.multiply(&acc, x));
+
+    multi_exponentiate(group, &outputs) == proofs
+}
+
+/// Multiplies b into a, where None stands for the identity. Skipping the
+/// identity saves a full group multiplication.
+fn multiply_into<G: VdfGroup>(
+    group: &G,
+    a: &mut Option<G::Element>,
+    b: &G::Element,
+) {
+    *a = Some(match a.take() {
+        None => b.clone(),
+        Some(a) => group.multiply(&a, b),
+    });
+}
+
+/// Calculates prod(base^exponent) with Pippenger's bucket method. The
+/// exponents are split into windows of c bits, and in each window the bases
+/// are sorted into buckets by their digit, so each base costs one
+/// multiplication per window.
+fn multi_exponentiate<G: VdfGroup>(
+    group: &G,
+    terms: &[(&G::Element, Int)],
+) -> G::Element {
+    let bits = terms
+        .iter()
+        .map(|(_, exponent)| exponent.bit_length())
+        .max()
+        .unwrap_or(0);
+    // The window grows with the number of terms, as combining the 2^c
+    // buckets is then shared by more terms
+    let c = match terms.len() {
+        0..=7 => 1,
+        n => 61 - (n as u64).leading_zeros(),
+    };
+
+    let mut result: Option<G::Element> = None;
+    for window in (0..(bits + c - 1) / c).rev() {
+        if let Some(r) = result.as_mut() {
+            for _ in 0..c {
+                *r = group.square(r);
+            }
+        }
+
+        let mut buckets: Vec<Option<G::Element>> = vec![None; 1 << c];
+        for (base, exponent) in terms {
+            let digit = (0..c)
+                .filter(|bit| exponent.bit(window * c + bit))
+                .fold(0usize, |digit, bit| digit | 1 << bit);
+            if digit > 0 {
+                multiply_into(group, &mut buckets[digit], base);
+            }
+        }
+
+        // prod(bucket_d^d) as a running product of suffix products
+        let mut suffix: Option<G::Element> = None;
+        for bucket in buckets.iter().skip(1).rev() {
+            if let Some(bucket) = bucket {
+                multiply_into(group, &mut suffix, bucket);
+            }
+            if let Some(suffix) = suffix.as_ref() {
+                multiply_into(group, &mut result, suffix);
+            }
+        }
+    }
+    result.unwrap_or_else(|| group.identity())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::evaluation::VDF;
+    use crate::vdf::group::RsaGroup;
+    use crate::vdf::util;
+    use crate::RSA_2048;
+
+    fn proofs(
+        modulus: &Int,
+        count: usize,
+        iterations: u64,
+    ) -> Vec<DeserializableVDFProof> {
+        let group = RsaGroup::new(modulus.clone());
+        let cap = Generator::new_safe_prime(128);
+        (0..count)
+            .map(|i| {
+                let generator =
+                    util::hash_to_mod(&format!("batch {}", i), modulus);
+                let result = VDF::new(
+                    group.clone(),
+                    generator.clone(),
+                    iterations,
+                    ProofType::Sequential,
+                )
+                .last()
+                .unwrap();
+                VDFProof::new(
+                    &group,
+                    &generator,
+                    &result,
+                    &cap,
+                    &ProofType::Sequential,
+                )
+                .calculate()
+                .unwrap()
+                .deserialize()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_batch_has_no_failures() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        assert!(verify_batch::<RsaGroup>(&proofs(&modulus, 8, 256)).is_empty());
+    }
+
+    #[test]
+    fn failing_proofs_are_found() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let mut batch = proofs(&modulus, 8, 256);
+        batch[2].pi = batch[3].pi.clone();
+        batch[5].output.iterations += 1;
+        batch[7].cap = "not a number".to_string();
+
+        // A proof in another group is verified on its own
+        let mut other =
+            proofs(&Int::from_str_radix("1000000007", 10).unwrap(), 1, 256);
+        other[0].pi = "2".to_string();
+        batch.extend(other);
+
+        assert_eq!(verify_batch::<RsaGroup>(&batch), vec![2, 5, 7, 8]);
+    }
+}
@@ -0,0 +1,266 @@
+//! The big integer backend. By default the arithmetic is done with ramp, which
+//! needs a nightly toolchain. With the num-backend feature the same API is
+//! provided by a pure-Rust implementation on top of num-bigint, so the rest
+//! of the crate doesn't need to know which one is in use.
+
+#[cfg(not(any(feature = "ramp-backend", feature = "num-backend")))]
+compile_error!("Either the ramp-backend or the num-backend feature is needed");
+
+#[cfg(not(feature = "num-backend"))]
+pub use ramp::Int;
+#[cfg(not(feature = "num-backend"))]
+pub use ramp_primes::{Generator, Verification};
+
+#[cfg(feature = "num-backend")]
+pub use self::num_backend::{Generator, Int, Verification};
+
+#[cfg(feature = "num-backend")]
+mod num_backend {
+    use num_bigint::{BigInt, ParseBigIntError, Sign};
+    use num_traits::{Num, One, Zero};
+    use rand::RngCore;
+    use std::fmt;
+    use std::ops::{Add, Div, Mul, Neg, Rem, Shl, Sub};
+    use std::str::FromStr;
+
+    /// Number of Miller-Rabin rounds used in primality tests
+    const MILLER_RABIN_ROUNDS: usize = 32;
+
+    const SMALL_PRIMES: [u32; 15] =
+        [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47];
+
+    /// A big integer exposing the parts of the ramp::Int API used in this
+    /// crate
+    #[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Int(BigInt);
+
+    impl Int {
+        pub fn zero() -> Self {
+            Int(BigInt::zero())
+        }
+
+        pub fn one() -> Self {
+            Int(BigInt::one())
+        }
+
+        pub fn from_str_radix(
+            s: &str,
+            radix: u8,
+        ) -> Result<Self, ParseBigIntError> {
+            BigInt::from_str_radix(s, u32::from(radix)).map(Int)
+        }
+
+        pub fn to_str_radix(&self, radix: u8, upper: bool) -> String {
+            let s = self.0.to_str_radix(u32::from(radix));
+            if upper {
+                s.to_uppercase()
+            } else {
+                s
+            }
+        }
+
+        pub fn pow_mod(&self, exponent: &Int, modulus: &Int) -> Int {
+            // modpow sets up Montgomery multiplication on every call, which
+            // is slower than the whole calculation for a single squaring
+            if exponent.0 == BigInt::from(2) {
+                return Int(&self.0 * &self.0 % &modulus.0);
+            }
+            Int(self.0.modpow(&exponent.0, &modulus.0))
+        }
+
+        pub fn bit_length(&self) -> u32 {
+            self.0.bits() as u32
+        }
+
+        pub fn bit(&self, bit: u32) -> bool {
+            self.0.bit(u64::from(bit))
+        }
+
+        pub fn set_bit(&mut self, bit: u32, value: bool) {
+            self.0.set_bit(u64::from(bit), value)
+        }
+    }
+
+    impl fmt::Display for Int {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Display::fmt(&self.0, f)
+        }
+    }
+
+    impl fmt::Debug for Int {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Display::fmt(&self.0, f)
+        }
+    }
+
+    impl FromStr for Int {
+        type Err = ParseBigIntError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Int::from_str_radix(s, 10)
+        }
+    }
+
+    macro_rules! impl_from {
+        ($($t:ty),*) => {
+            $(
+                impl From<$t> for Int {
+                    fn from(n: $t) -> Self {
+                        Int(BigInt::from(n))
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_from!(u8, u32, u64, usize, i32, i64);
+
+    impl PartialEq<i32> for Int {
+        fn eq(&self, other: &i32) -> bool {
+            self.0 == BigInt::from(*other)
+        }
+    }
+
+    macro_rules! impl_binop {
+        ($($imp:ident, $method:ident);*) => {
+            $(
+                impl $imp<Int> for Int {
+                    type Output = Int;
+                    fn $method(self, other: Int) -> Int {
+                        Int(self.0.$method(other.0))
+                    }
+                }
+
+                impl<'a> $imp<&'a Int> for Int {
+                    type Output = Int;
+                    fn $method(self, other: &Int) -> Int {
+                        Int(self.0.$method(&other.0))
+                    }
+                }
+
+                impl<'a> $imp<Int> for &'a Int {
+                    type Output = Int;
+                    fn $method(self, other: Int) -> Int {
+                        Int((&self.0).$method(other.0))
+                    }
+                }
+
+                impl<'a, 'b> $imp<&'b Int> for &'a Int {
+                    type Output = Int;
+                    fn $method(self, other: &Int) -> Int {
+                        Int((&self.0).$method(&other.0))
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_binop!(Add, add; Sub, sub; Mul, mul; Div, div; Rem, rem);
+
+    impl Shl<usize> for Int {
+        type Output = Int;
+        fn shl(self, bits: usize) -> Int {
+            Int(self.0 << bits)
+        }
+    }
+
+    impl<'a> Shl<usize> for &'a Int {
+        type Output = Int;
+        fn shl(self, bits: usize) -> Int {
+            Int(&self.0 << bits)
+        }
+    }
+
+    impl Neg for Int {
+        type Output = Int;
+        fn neg(self) -> Int {
+            Int(-self.0)
+        }
+    }
+
+    /// Returns a uniformly distributed integer in [0, bound)
+    fn random_below(bound: &BigInt) -> BigInt {
+        // Extra bytes make the modulo bias negligible
+        let mut bytes = vec![0u8; (bound.bits() as usize + 7) / 8 + 8];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        BigInt::from_bytes_be(Sign::Plus, &bytes) % bound
+    }
+
+    fn is_probable_prime(n: &BigInt) -> bool {
+        let two = BigInt::from(2);
+        if *n < two {
+            return false;
+        }
+        for p in SMALL_PRIMES.iter() {
+            let p = BigInt::from(*p);
+            if *n == p {
+                return true;
+            }
+            if (n % &p).is_zero() {
+                return false;
+            }
+        }
+
+        let n_minus_one = n - BigInt::one();
+        let s = n_minus_one.trailing_zeros().unwrap_or(0);
+        let d = &n_minus_one >> s;
+
+        'witness: for _ in 0..MILLER_RABIN_ROUNDS {
+            let a = random_below(&(n - BigInt::from(3))) + &two;
+            let mut x = a.modpow(&d, n);
+            if x.is_one() || x == n_minus_one {
+                continue;
+            }
+            for _ in 1..s {
+                x = x.modpow(&two, n);
+                if x == n_minus_one {
+                    continue 'witness;
+                }
+            }
+            return false;
+        }
+        true
+    }
+
+    /// Random number and prime generation, like ramp_primes::Generator
+    pub struct Generator;
+
+    impl Generator {
+        /// Returns a random integer of exactly bits bits
+        pub fn new_uint(bits: usize) -> Int {
+            let mut n = Int(random_below(&(BigInt::one() << bits)));
+            n.set_bit(bits as u32 - 1, true);
+            n
+        }
+
+        pub fn new_prime(bits: usize) -> Int {
+            loop {
+                let mut candidate = Self::new_uint(bits);
+                candidate.set_bit(0, true);
+                if is_probable_prime(&candidate.0) {
+                    return candidate;
+                }
+            }
+        }
+
+        /// Returns a prime p = 2q + 1, where q is also a prime
+        pub fn new_safe_prime(bits: usize) -> Int {
+            loop {
+                let q = Self::new_prime(bits - 1);
+                let p = Int(&q.0 * BigInt::from(2) + BigInt::one());
+                if is_probable_prime(&p.0) {
+                    return p;
+                }
+            }
+        }
+    }
+
+    /// Primality testing, like ramp_primes::Verification
+    pub struct Verification;
+
+    impl Verification {
+        pub fn verify_prime(n: Int) -> bool {
+            is_probable_prime(&n.0)
+        }
+    }
+}
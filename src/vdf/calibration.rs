@@ -0,0 +1,182 @@
+//! Conversion of iteration counts to wall clock time. The speed of squarings
+//! is sampled by running a VDF for a while on the current machine, and the
+//! samples are kept so the conversion can give a confidence interval instead
+//! of a single number.
+use crate::vdf::evaluation::VDF;
+use crate::vdf::group::VdfGroup;
+use crate::vdf::proof::ProofType;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Default number of samples taken by Calibration::measure
+pub const DEFAULT_SAMPLES: usize = 10;
+
+/// Default length of a single sample in milliseconds
+pub const DEFAULT_SAMPLE_MS: u64 = 100;
+
+/// Confidence level of a TimeBound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+    Ninety,
+    NinetyFive,
+    NinetyNine,
+}
+
+impl Confidence {
+    /// Two-sided z-score of the confidence level
+    fn z(self) -> f64 {
+        match self {
+            Confidence::Ninety => 1.645,
+            Confidence::NinetyFive => 1.960,
+            Confidence::NinetyNine => 2.576,
+        }
+    }
+}
+
+/// An iteration count converted to milliseconds. upper_ms is infinite when the
+/// samples vary too much to bound the speed of squarings from below.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeBound {
+    pub estimate_ms: f64,
+    pub lower_ms: f64,
+    pub upper_ms: f64,
+}
+
+/// Measured speed of squarings in a group of bit_length bits, each sample
+/// being the number of squarings per second over sample_ms milliseconds
+#[derive(
+    Archive, Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[archive(derive(CheckBytes))]
+pub struct Calibration {
+    pub bit_length: u32,
+    pub sample_ms: u64,
+    pub samples: Vec<u64>,
+}
+
+impl Calibration {
+    /// Measures the speed of squarings in group by running a VDF for
+    /// sample_ms milliseconds, samples times. The evaluation just stops when
+    /// the time is up, no proof is calculated.
+    pub fn measure<G: VdfGroup>(
+        group: &G,
+        samples: usize,
+        sample_ms: u64,
+    ) -> Self {
+        let generator = group.hash_to_element("calibration");
+        let duration = Duration::from_millis(sample_ms.max(1));
+        let samples = (0..samples)
+            .map(|_| {
+                let vdf = VDF::new(
+                    group.clone(),
+                    generator.clone(),
+                    u64::MAX,
+                    ProofType::Sequential,
+                );
+                let started = Instant::now();
+                let iterations =
+                    vdf.take_while(|_| started.elapsed() < duration).count();
+                (iterations as f64 / started.elapsed().as_secs_f64()) as u64
+            })
+            .collect();
+        debug!("Calibrated squarings per second: {:?}", samples);
+        Self {
+            bit_length: group.bit_length(),
+            sample_ms,
+            samples,
+        }
+    }
+
+    /// Mean squarings per second
+    pub fn rate(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().map(|&s| s as f64).sum::<f64>()
+            / self.samples.len() as f64
+    }
+
+    /// Standard error of the mean squarings per second
+    pub fn standard_error(&self) -> f64 {
+        let n = self.samples.len() as f64;
+        if n < 2.0 {
+            return 0.0;
+        }
+        let rate = self.rate();
+        let variance = self
+            .samples
+            .iter()
+            .map(|&s| (s as f64 - rate).powi(2))
+            .sum::<f64>()
+            / (n - 1.0);
+        (variance / n).sqrt()
+    }
+
+    /// Converts an iteration count to milliseconds. The interval is a normal
+    /// approximation, so it is only meaningful with ten or so samples.
+    pub fn time_bound(
+        &self,
+        iterations: u64,
+        confidence: Confidence,
+    ) -> TimeBound {
+        let ms = |rate: f64| {
+            if rate > 0.0 {
+                iterations as f64 * 1000.0 / rate
+            } else {
+                f64::INFINITY
+            }
+        };
+        let margin = confidence.z() * self.standard_error();
+        let rate = self.rate();
+        TimeBound {
+            estimate_ms: ms(rate),
+            lower_ms: ms(rate + margin),
+            upper_ms: ms(rate - margin),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::bigint::Int;
+    use crate::vdf::group::RsaGroup;
+    use crate::RSA_2048;
+    use std::str::FromStr;
+
+    #[test]
+    fn time_bound_contains_the_estimate() {
+        let calibration = Calibration {
+            bit_length: 2048,
+            sample_ms: 100,
+            samples: vec![9000, 10000, 11000, 10000],
+        };
+        assert_eq!(calibration.rate(), 10000.0);
+
+        let bound = calibration.time_bound(5000, Confidence::NinetyFive);
+        assert_eq!(bound.estimate_ms, 500.0);
+        assert!(bound.lower_ms < bound.estimate_ms);
+        assert!(bound.upper_ms > bound.estimate_ms);
+
+        let wider = calibration.time_bound(5000, Confidence::NinetyNine);
+        assert!(wider.lower_ms < bound.lower_ms);
+        assert!(wider.upper_ms > bound.upper_ms);
+    }
+
+    #[test]
+    fn uncalibrated_time_is_unbounded() {
+        let bound =
+            Calibration::default().time_bound(1, Confidence::NinetyFive);
+        assert_eq!(bound.upper_ms, f64::INFINITY);
+    }
+
+    #[test]
+    fn measures_the_current_machine() {
+        let group = RsaGroup::new(Int::from_str(RSA_2048).unwrap());
+        let calibration = Calibration::measure(&group, 2, 20);
+        assert_eq!(calibration.bit_length, group.bit_length());
+        assert_eq!(calibration.samples.len(), 2);
+        assert!(calibration.rate() > 0.0);
+    }
+}
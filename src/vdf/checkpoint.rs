@@ -0,0 +1,177 @@
+use crate::identity::Transcript;
+use crate::vdf::bigint::Int;
+use crate::vdf::group::VdfGroup;
+use std::path::Path;
+
+/// State of the parallel proof calculator after a number of nudges, enough to
+/// continue calculating pi from where it was left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParallelProofState<E> {
+    pub iterations: u64,
+    pub r: Int,
+    pub pi: E,
+}
+
+/// A snapshot of a VDF evaluation. The proof state is only present if the
+/// parallel proof calculator had reached the same iteration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint<E> {
+    pub iterations: u64,
+    pub result: E,
+    pub proof_state: Option<ParallelProofState<E>>,
+}
+
+/// Key of the statement digest in the statement tree
+const STATEMENT_KEY: &[u8] = b"statement";
+
+/// Digest of the statement a VDF evaluation proves, the group, the generator
+/// and the cap. Checkpoints of one statement are useless for any other.
+pub fn statement_digest<G: VdfGroup>(
+    group: &G,
+    generator: &G::Element,
+    cap: &Int,
+) -> [u8; 32] {
+    Transcript::new("CheckpointStatement")
+        .field(group.encode_group().as_bytes())
+        .field(group.encode(generator).as_bytes())
+        .field(cap.to_str_radix(10, false).as_bytes())
+        .finish()
+}
+
+/// Persists VDF checkpoints of one evaluation session in sled. The evaluator
+/// and the parallel proof calculator run in separate threads, so both write
+/// their own state into their own tree, keyed by the big endian iteration
+/// count. The session is bound to the statement digest of the first VDF
+/// using it.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    evaluation: sled::Tree,
+    proof: sled::Tree,
+    statement: sled::Tree,
+}
+
+impl CheckpointStore {
+    /// Opens the checkpoints of the session from the sled database at path
+    pub fn open<P: AsRef<Path>>(path: P, session: &str) -> sled::Result<Self> {
+        Self::from_db(&sled::open(path)?, session)
+    }
+
+    /// Opens the checkpoints of the session from an already open database
+    pub fn from_db(db: &sled::Db, session: &str) -> sled::Result<Self> {
+        Ok(Self {
+            evaluation: db.open_tree(format!("{}/evaluation", session))?,
+            proof: db.open_tree(format!("{}/proof", session))?,
+            statement: db.open_tree(format!("{}/statement", session))?,
+        })
+    }
+
+    /// Binds the session to the statement digest, or checks that it already
+    /// is. Returns false if the checkpoints belong to another statement.
+    pub fn bind(&self, digest: &[u8; 32]) -> sled::Result<bool> {
+        let bound = self.statement.compare_and_swap(
+            STATEMENT_KEY,
+            None as Option<&[u8]>,
+            Some(&digest[..]),
+        )?;
+        let matches = match bound {
+            Ok(()) => true,
+            Err(existing) => existing.current.as_deref() == Some(&digest[..]),
+        };
+        self.statement.flush()?;
+        Ok(matches)
+    }
+
+    pub fn save_evaluation<G: VdfGroup>(
+        &self,
+        group: &G,
+        iterations: u64,
+        result: &G::Element,
+    ) -> sled::Result<()> {
+        self.evaluation.insert(
+            iterations.to_be_bytes(),
+            group.encode(result).as_bytes(),
+        )?;
+        self.evaluation.flush()?;
+        Ok(())
+    }
+
+    pub fn save_proof_state<G: VdfGroup>(
+        &self,
+        group: &G,
+        state: &ParallelProofState<G::Element>,
+    ) -> sled::Result<()> {
+        let value = format!(
+            "{}\n{}",
+            state.r.to_str_radix(10, false),
+            group.encode(&state.pi)
+        );
+        self.proof
+            .insert(state.iterations.to_be_bytes(), value.as_bytes())?;
+        self.proof.flush()?;
+        Ok(())
+    }
+
+    fn load_proof_state<G: VdfGroup>(
+        &self,
+        group: &G,
+        iterations: u64,
+    ) -> sled::Result<Option<ParallelProofState<G::Element>>> {
+        let value = match self.proof.get(iterations.to_be_bytes())? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let value = String::from_utf8_lossy(&value);
+        let mut parts = value.splitn(2, '\n');
+        let r = parts.next().and_then(|r| Int::from_str_radix(r, 10).ok());
+        let pi = parts.next().and_then(|pi| group.decode(pi));
+        Ok(match (r, pi) {
+            (Some(r), Some(pi)) => {
+                Some(ParallelProofState { iterations, r, pi })
+            }
+            _ => None,
+        })
+    }
+
+    /// Returns the latest checkpoint of the session. With with_proof_state
+    /// only checkpoints that the parallel proof calculator has also reached
+    /// are considered.
+    pub fn latest<G: VdfGroup>(
+        &self,
+        group: &G,
+        with_proof_state: bool,
+    ) -> sled::Result<Option<Checkpoint<G::Element>>> {
+        for entry in self.evaluation.iter().rev() {
+            let (key, value) = entry?;
+            let mut bytes = [0u8; 8];
+            if key.len() != bytes.len() {
+                continue;
+            }
+            bytes.copy_from_slice(&key);
+            let iterations = u64::from_be_bytes(bytes);
+
+            let result = match group.decode(&String::from_utf8_lossy(&value)) {
+                Some(result) => result,
+                None => continue,
+            };
+            let proof_state = self.load_proof_state(group, iterations)?;
+            if with_proof_state && proof_state.is_none() {
+                continue;
+            }
+
+            return Ok(Some(Checkpoint {
+                iterations,
+                result,
+                proof_state,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Removes all checkpoints of the session, unbinding it from its
+    /// statement
+    pub fn clear(&self) -> sled::Result<()> {
+        self.evaluation.clear()?;
+        self.proof.clear()?;
+        self.statement.clear()
+    }
+}
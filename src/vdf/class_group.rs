@@ -0,0 +1,429 @@
+use crate::vdf::bigint::Int;
+use crate::vdf::bigint::Verification;
+use crate::vdf::group::VdfGroup;
+use crate::vdf::util;
+
+/// Floor division, independent of the rounding used by the Int backend
+fn floor_div(a: &Int, b: &Int) -> Int {
+    let q = a / b;
+    let r = a - &q * b;
+    if r != Int::zero() && ((r < Int::zero()) != (*b < Int::zero())) {
+        q - Int::one()
+    } else {
+        q
+    }
+}
+
+/// Modulo with the sign of the divisor, like floor division
+fn floor_mod(a: &Int, b: &Int) -> Int {
+    a - floor_div(a, b) * b
+}
+
+fn abs(a: &Int) -> Int {
+    if *a < Int::zero() {
+        Int::zero() - a
+    } else {
+        a.clone()
+    }
+}
+
+/// Integer square root with Newton's method
+fn isqrt(n: &Int) -> Int {
+    let two = Int::from(2);
+    if *n < two {
+        return n.clone();
+    }
+    let mut x = Int::one() << ((n.bit_length() as usize + 1) / 2);
+    loop {
+        let y = (&x + n / &x) / &two;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Extended Euclidean algorithm, returns (g, x, y) with a * x + b * y = g >= 0
+fn xgcd(a: &Int, b: &Int) -> (Int, Int, Int) {
+    let (mut old_r, mut r) = (a.clone(), b.clone());
+    let (mut old_x, mut x) = (Int::one(), Int::zero());
+    let (mut old_y, mut y) = (Int::zero(), Int::one());
+
+    while r != Int::zero() {
+        let q = floor_div(&old_r, &r);
+        let next_r = &old_r - &q * &r;
+        old_r = std::mem::replace(&mut r, next_r);
+        let next_x = &old_x - &q * &x;
+        old_x = std::mem::replace(&mut x, next_x);
+        let next_y = &old_y - &q * &y;
+        old_y = std::mem::replace(&mut y, next_y);
+    }
+
+    if old_r < Int::zero() {
+        (
+            Int::zero() - old_r,
+            Int::zero() - old_x,
+            Int::zero() - old_y,
+        )
+    } else {
+        (old_r, old_x, old_y)
+    }
+}
+
+/// A binary quadratic form ax^2 + bxy + cy^2. Reduced forms of a negative
+/// discriminant D are the elements of the class group of the imaginary
+/// quadratic field of discriminant D.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuadraticForm {
+    pub a: Int,
+    pub b: Int,
+    pub c: Int,
+}
+
+impl QuadraticForm {
+    pub fn new(a: Int, b: Int, c: Int) -> Self {
+        Self { a, b, c }
+    }
+
+    /// Calculates b^2 - 4ac
+    pub fn discriminant(&self) -> Int {
+        &self.b * &self.b - Int::from(4) * &self.a * &self.c
+    }
+
+    fn normalize(self) -> Self {
+        let two_a = Int::from(2) * &self.a;
+        let r = floor_div(&(&self.a - &self.b), &two_a);
+        let b = &self.b + &r * &two_a;
+        let c = &self.a * &r * &r + &self.b * &r + &self.c;
+        Self { a: self.a, b, c }
+    }
+
+    fn is_normal(&self) -> bool {
+        Int::zero() - &self.a < self.b && self.b <= self.a
+    }
+
+    /// Reduces the form to the unique representative of its class, with
+    /// -a < b <= a <= c, and b >= 0 if a = c
+    pub fn reduce(self) -> Self {
+        let mut form = self.normalize();
+        while form.a > form.c || (form.a == form.c && form.b < Int::zero()) {
+            let s = floor_div(&(&form.c + &form.b), &(Int::from(2) * &form.c));
+            let b = Int::zero() - &form.b + Int::from(2) * &s * &form.c;
+            let c = &form.c * &s * &s - &form.b * &s + &form.a;
+            form = Self { a: form.c, b, c };
+        }
+        form.normalize()
+    }
+
+    pub fn is_reduced(&self) -> bool {
+        self.is_normal()
+            && (self.a < self.c || (self.a == self.c && self.b >= Int::zero()))
+    }
+
+    /// Encodes the form as decimal coefficients separated by commas
+    pub fn encode(&self) -> String {
+        format!(
+            "{},{},{}",
+            self.a.to_str_radix(10, false),
+            self.b.to_str_radix(10, false),
+            self.c.to_str_radix(10, false)
+        )
+    }
+
+    /// Decodes a form encoded with QuadraticForm::encode
+    pub fn decode(s: &str) -> Option<Self> {
+        let coefficients: Vec<Int> = s
+            .split(',')
+            .map(|part| Int::from_str_radix(part, 10).ok())
+            .collect::<Option<Vec<Int>>>()?;
+        match coefficients.as_slice() {
+            [a, b, c] => Some(Self::new(a.clone(), b.clone(), c.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// The class group of discriminant D. Computing its order is believed to be
+/// infeasible for a large enough D, so a VDF over it needs no trusted setup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassGroup {
+    pub discriminant: Int,
+    /// Partial reduction bound for NUDUPL, |D/4|^(1/4)
+    bound: Int,
+}
+
+impl ClassGroup {
+    /// Creates a class group from a discriminant D < 0 with D = 1 mod 8
+    pub fn new(discriminant: Int) -> Self {
+        let bound = isqrt(&isqrt(&(abs(&discriminant) / Int::from(4))));
+        Self {
+            discriminant,
+            bound,
+        }
+    }
+
+    /// Deterministically derives the class group from a seed. The
+    /// discriminant is -p for a prime p = 7 mod 8 of bit_length bits, found
+    /// like vdf::util::hash_to_prime does.
+    pub fn from_seed(seed: &str, bit_length: u32) -> Self {
+        let n = bit_length as usize;
+        let mut candidate = util::hash_to_int(seed, bit_length);
+
+        loop {
+            candidate = candidate % (Int::one() << n);
+            candidate.set_bit(n as u32 - 1, true);
+            candidate.set_bit(0, true);
+            candidate.set_bit(1, true);
+            candidate.set_bit(2, true);
+            if Verification::verify_prime(candidate.clone()) {
+                break;
+            }
+            candidate = util::hash_to_int(
+                &candidate.to_str_radix(10, false),
+                bit_length,
+            );
+        }
+
+        Self::new(Int::zero() - candidate)
+    }
+
+    /// The form (2, 1, (1 - D) / 8), which exists because D = 1 mod 8
+    pub fn generator(&self) -> QuadraticForm {
+        QuadraticForm::new(
+            Int::from(2),
+            Int::one(),
+            (Int::one() - &self.discriminant) / Int::from(8),
+        )
+        .reduce()
+    }
+
+    pub fn inverse(&self, form: &QuadraticForm) -> QuadraticForm {
+        QuadraticForm::new(
+            form.a.clone(),
+            Int::zero() - &form.b,
+            form.c.clone(),
+        )
+        .reduce()
+    }
+
+    /// Composes two forms with Shanks' algorithm (Cohen, Algorithm 5.4.7)
+    pub fn compose(
+        &self,
+        f1: &QuadraticForm,
+        f2: &QuadraticForm,
+    ) -> QuadraticForm {
+        let (f1, f2) = if f1.a > f2.a { (f2, f1) } else { (f1, f2) };
+        let two = Int::from(2);
+        let s = (&f1.b + &f2.b) / &two;
+        let n = &f2.b - &s;
+
+        let (y1, d) = if floor_mod(&f2.a, &f1.a) == Int::zero() {
+            (Int::zero(), f1.a.clone())
+        } else {
+            let (d, u, _) = xgcd(&f2.a, &f1.a);
+            (u, d)
+        };
+
+        let (x2, y2, d1) = if floor_mod(&s, &d) == Int::zero() {
+            (Int::zero(), Int::zero() - Int::one(), d)
+        } else {
+            let (d1, u, v) = xgcd(&s, &d);
+            (u, Int::zero() - v, d1)
+        };
+
+        let v1 = &f1.a / &d1;
+        let v2 = &f2.a / &d1;
+        let r = floor_mod(&(&y1 * &y2 * &n - &x2 * &f2.c), &v1);
+        let b3 = &f2.b + &two * &v2 * &r;
+        let a3 = &v1 * &v2;
+        let c3 = (&b3 * &b3 - &self.discriminant) / (Int::from(4) * &a3);
+
+        QuadraticForm::new(a3, b3, c3).reduce()
+    }
+
+    /// Squares a reduced form with NUDUPL (Cohen, Algorithm 5.4.8), which
+    /// keeps the intermediate coefficients around the size of |D|^(1/4)
+    pub fn nudupl(&self, form: &QuadraticForm) -> QuadraticForm {
+        let (a, b, c) = (&form.a, &form.b, &form.c);
+        let (d1, u, _) = xgcd(b, a);
+        let big_a = a / &d1;
+        let big_b = b / &d1;
+        let mut big_c = floor_mod(&(Int::zero() - c * &u), &big_a);
+        let c1 = &big_a - &big_c;
+        if c1 < big_c {
+            big_c = Int::zero() - c1;
+        }
+
+        // Partial Euclidean reduction of (A, C)
+        let mut v = Int::zero();
+        let mut d = big_a.clone();
+        let mut v2 = Int::one();
+        let mut v3 = big_c;
+        let mut z = 0u32;
+        while abs(&v3) > self.bound {
+            let q = floor_div(&d, &v3);
+            let t3 = &d - &q * &v3;
+            let t2 = &v - &q * &v2;
+            v = v2;
+            d = v3;
+            v2 = t2;
+            v3 = t3;
+            z += 1;
+        }
+        if z % 2 == 1 {
+            v2 = Int::zero() - v2;
+            v3 = Int::zero() - v3;
+        }
+
+        if z == 0 {
+            let g = floor_div(&(&big_b * &v3 + c), &d);
+            let a2 = &d * &d;
+            let c2 = &v3 * &v3;
+            let d_v3 = &d + &v3;
+            let b2 = b + &d_v3 * &d_v3 - &a2 - &c2;
+            let c2 = c2 + &g * &d1;
+            return QuadraticForm::new(a2, b2, c2).reduce();
+        }
+
+        let e = floor_div(&(c * &v + &big_b * &d), &big_a);
+        let g = floor_div(&(&e * &v2 - &big_b), &v);
+        let mut b2 = &e * &v2 + &v * &g;
+        if d1 > Int::one() {
+            b2 = &d1 * b2;
+            v = &d1 * v;
+            v2 = &d1 * v2;
+        }
+        let a2 = &d * &d;
+        let c2 = &v3 * &v3;
+        let d_v3 = &d + &v3;
+        let b2 = b2 + &d_v3 * &d_v3 - &a2 - &c2;
+        let a2 = a2 + &e * &v;
+        let c2 = c2 + &g * &v2;
+        QuadraticForm::new(a2, b2, c2).reduce()
+    }
+}
+
+impl VdfGroup for ClassGroup {
+    type Element = QuadraticForm;
+
+    /// The principal form (1, 1, (1 - D) / 4)
+    fn identity(&self) -> QuadraticForm {
+        QuadraticForm::new(
+            Int::one(),
+            Int::one(),
+            (Int::one() - &self.discriminant) / Int::from(4),
+        )
+    }
+
+    fn square(&self, x: &QuadraticForm) -> QuadraticForm {
+        self.nudupl(x)
+    }
+
+    fn multiply(&self, a: &QuadraticForm, b: &QuadraticForm) -> QuadraticForm {
+        self.compose(a, b)
+    }
+
+    fn encode(&self, x: &QuadraticForm) -> String {
+        x.encode()
+    }
+
+    fn decode(&self, s: &str) -> Option<QuadraticForm> {
+        QuadraticForm::decode(s)
+    }
+
+    /// Checks that the form is a reduced form of our discriminant
+    fn is_element(&self, x: &QuadraticForm) -> bool {
+        x.a > Int::zero()
+            && x.discriminant() == self.discriminant
+            && x.is_reduced()
+    }
+
+    /// Raises the generator form to a hashed power, a hash to a prime form
+    /// would need square roots modulo the prime
+    fn hash_to_element(&self, s: &str) -> QuadraticForm {
+        let exponent = util::hash_to_int(s, self.discriminant.bit_length());
+        self.exponentiate(&self.generator(), &exponent)
+    }
+
+    fn encode_group(&self) -> String {
+        self.discriminant.to_str_radix(10, false)
+    }
+
+    fn bit_length(&self) -> u32 {
+        self.discriminant.bit_length()
+    }
+
+    fn decode_group(s: &str) -> Option<Self> {
+        let discriminant = Int::from_str_radix(s, 10).ok()?;
+        if discriminant < Int::zero()
+            && floor_mod(&discriminant, &Int::from(8)) == Int::one()
+        {
+            Some(Self::new(discriminant))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::evaluation::VDF;
+    use crate::vdf::proof::{ProofType, VDFProof};
+
+    #[test]
+    fn nudupl_agrees_with_composition() {
+        let group = ClassGroup::from_seed("nudupl", 256);
+        let mut form = group.generator();
+        for _ in 0..200 {
+            let squared = group.nudupl(&form);
+            assert_eq!(squared, group.compose(&form, &form));
+            assert!(group.is_element(&squared));
+            form = squared;
+        }
+    }
+
+    #[test]
+    fn discriminant_is_deterministic_and_one_mod_eight() {
+        let group = ClassGroup::from_seed("seed", 512);
+        assert_eq!(group, ClassGroup::from_seed("seed", 512));
+        assert!(group != ClassGroup::from_seed("other seed", 512));
+        assert_eq!(floor_mod(&group.discriminant, &Int::from(8)), Int::one());
+        assert!(group.is_element(&group.generator()));
+        assert_eq!(
+            ClassGroup::decode_group(&group.encode_group()),
+            Some(group)
+        );
+    }
+
+    #[test]
+    fn form_times_inverse_is_identity() {
+        let group = ClassGroup::from_seed("inverse", 256);
+        let form = group.hash_to_element("some input");
+        let inverse = group.inverse(&form);
+        assert_eq!(group.multiply(&form, &inverse), group.identity());
+        assert_eq!(group.decode(&group.encode(&form)), Some(form));
+    }
+
+    #[test]
+    fn vdf_runs_over_class_group() {
+        let group = ClassGroup::from_seed("wesolowski", 512);
+        let generator = group.hash_to_element("generator");
+
+        let vdf = VDF::new(group, generator, 200, ProofType::Sequential)
+            .non_interactive()
+            .unwrap();
+        let (_, receiver) = vdf.run_vdf_worker();
+
+        if let Ok(Ok(proof)) = receiver.recv() {
+            assert!(proof.verify_non_interactive());
+            assert!(proof.deserialize().verify_non_interactive::<ClassGroup>());
+
+            let mut forged: VDFProof<ClassGroup> = proof.clone();
+            forged.output.result = proof.group.square(&forged.output.result);
+            assert!(!forged.verify());
+        } else {
+            panic!("The VDF worker didn't return a proof");
+        }
+    }
+}
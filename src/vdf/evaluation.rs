@@ -1,66 +1,83 @@
+use crate::error::PoLError;
 use crate::vdf;
+use crate::vdf::bigint::Generator;
+use crate::vdf::bigint::Int;
+use crate::vdf::bigint::Verification;
+use crate::vdf::checkpoint::{self, CheckpointStore, ParallelProofState};
+use crate::vdf::group::{RsaGroup, VdfGroup};
+use bytecheck::CheckBytes;
 use crossbeam::channel::unbounded;
 use crossbeam::channel::{Receiver, Sender};
-use ramp::Int;
-use ramp_primes::Generator;
-use ramp_primes::Verification;
+use futures::channel::mpsc;
 use rkyv::{Archive, Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::time::Instant;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{thread, time};
 
 /// The end result of the VDF which we still need to prove
 #[derive(Debug, Clone, Default)]
-pub struct VDFResult {
-    pub result: Int,
-    pub iterations: u32,
+pub struct VDFResult<E = Int> {
+    pub result: E,
+    pub iterations: u64,
 }
 
-/// A deserializable VDFResult because ramp::Int is not deserializable
+/// A deserializable VDFResult because group elements such as ramp::Int are not
+/// deserializable
 #[derive(Archive, Debug, Deserialize, Serialize, Clone, Default)]
+#[archive(derive(CheckBytes))]
 pub struct DeserializableVDFResult {
     pub result: String,
-    pub iterations: u32,
+    pub iterations: u64,
 }
 
 impl DeserializableVDFResult {
-    pub fn serialize(&self) -> VDFResult {
-        VDFResult {
-            result: Int::from_str_radix(&self.result, 10).unwrap(),
+    pub fn serialize<G: VdfGroup>(
+        &self,
+        group: &G,
+    ) -> Result<VDFResult<G::Element>, PoLError> {
+        Ok(VDFResult {
+            result: group
+                .decode(&self.result)
+                .ok_or(PoLError::InvalidGroupElement)?,
             iterations: self.iterations,
-        }
+        })
     }
 }
 
 /// Traits that make calculating differences between VDFResults easier
-impl Ord for VDFResult {
+impl<E: Eq> Ord for VDFResult<E> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.iterations.cmp(&other.iterations)
     }
 }
 
-impl PartialOrd for VDFResult {
+impl<E: Eq> PartialOrd for VDFResult<E> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl PartialEq for VDFResult {
+impl<E: PartialEq> PartialEq for VDFResult<E> {
     fn eq(&self, other: &Self) -> bool {
         self.result == other.result && self.iterations == other.iterations
     }
 }
 
-impl VDFResult {
-    pub fn deserialize(&self) -> DeserializableVDFResult {
+impl<E> VDFResult<E> {
+    pub fn deserialize<G: VdfGroup<Element = E>>(
+        &self,
+        group: &G,
+    ) -> DeserializableVDFResult {
         DeserializableVDFResult {
-            result: self.result.to_str_radix(10, false),
+            result: group.encode(&self.result),
             iterations: self.iterations,
         }
     }
 }
 
-impl Eq for VDFResult {}
+impl<E: Eq> Eq for VDFResult<E> {}
 
 impl PartialEq for DeserializableVDFResult {
     fn eq(&self, other: &Self) -> bool {
@@ -70,27 +87,132 @@ impl PartialEq for DeserializableVDFResult {
 
 impl Eq for DeserializableVDFResult {}
 
-/// VDF is an options struct for calculating VDFProofs
+/// Handle for cancelling a running VDF worker. Clones share the same flag, so
+/// one handle can cancel several VDFs.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops the evaluator and its parallel proof calculator, the worker
+    /// sending PoLError::Cancelled instead of a proof
+    pub fn cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(atomic::Ordering::Relaxed)
+    }
+}
+
+/// How many iterations the evaluator runs between looking at the clock for a
+/// progress report
+const PROGRESS_CHECK_ITERATIONS: u64 = 256;
+
+/// Progress of a running VDF evaluation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub iterations: u64,
+    pub elapsed: Duration,
+    /// Estimated from all squarings since the worker started
+    pub squarings_per_second: f64,
+}
+
+/// Sends a Progress every interval, looking at the clock only every
+/// PROGRESS_CHECK_ITERATIONS iterations
+#[derive(Debug)]
+struct ProgressReporter {
+    sender: Sender<Progress>,
+    interval: Duration,
+    started: Instant,
+    first_iteration: u64,
+    next_report: Instant,
+}
+
+impl ProgressReporter {
+    fn new(
+        sender: Sender<Progress>,
+        interval: Duration,
+        iterations: u64,
+    ) -> Self {
+        let started = Instant::now();
+        Self {
+            sender,
+            interval,
+            started,
+            first_iteration: iterations,
+            next_report: started + interval,
+        }
+    }
+
+    /// Returns false once nobody listens to the reports anymore
+    fn report(&mut self, iterations: u64) -> bool {
+        if iterations % PROGRESS_CHECK_ITERATIONS != 0 {
+            return true;
+        }
+        let now = Instant::now();
+        if now < self.next_report {
+            return true;
+        }
+        self.next_report = now + self.interval;
+        let elapsed = now - self.started;
+        let squarings = (iterations - self.first_iteration) as f64;
+        self.sender
+            .send(Progress {
+                iterations,
+                elapsed,
+                squarings_per_second: squarings / elapsed.as_secs_f64(),
+            })
+            .is_ok()
+    }
+}
+
+/// What a VDF worker sends to its caller when the evaluation ends
+pub type WorkerResult<G> = Result<vdf::proof::VDFProof<G>, PoLError>;
+
+/// Window size of windowed proofs, unless set with VDF::with_window
+pub const DEFAULT_WINDOW: u64 = 8;
+
+/// VDF is an options struct for calculating VDFProofs in the group G
 #[derive(Debug, Clone)]
-pub struct VDF {
-    pub modulus: Int,
-    pub generator: Int,
-    pub upper_bound: u32,
+pub struct VDF<G: VdfGroup = RsaGroup> {
+    pub group: G,
+    pub generator: G::Element,
+    pub upper_bound: u64,
     pub cap: Int,
-    pub result: VDFResult,
-    two: Int,
+    pub result: VDFResult<G::Element>,
     pub proof_type: vdf::proof::ProofType,
+    pub challenge: vdf::proof::ChallengeType,
     proof_nudger: Option<Sender<bool>>,
-    proof_receiver: Option<Receiver<vdf::proof::VDFProof>>,
+    proof_receiver: Option<Receiver<vdf::proof::VDFProof<G>>>,
+    checkpoints: Option<(CheckpointStore, u64)>,
+    resumed_proof_state: Option<ParallelProofState<G::Element>>,
+    window: u64,
+    pietrzak_spacing: u64,
+    window_powers: Vec<G::Element>,
+    cancellation: Cancellation,
 }
 
-impl Iterator for VDF {
-    type Item = VDFResult;
-    fn next(&mut self) -> Option<VDFResult> {
+impl<G: VdfGroup> Iterator for VDF<G> {
+    type Item = VDFResult<G::Element>;
+    fn next(&mut self) -> Option<VDFResult<G::Element>> {
         if self.result.iterations < self.upper_bound {
+            // Windowed and Pietrzak proofs are calculated from powers of the
+            // generator stored at regular intervals
+            let interval = self.power_interval();
+            if interval > 0
+                && self.result.iterations % interval == 0
+                && self.window_powers.len() < self.max_stored_powers()
+            {
+                self.window_powers.push(self.result.result.clone());
+            }
             self.result.iterations += 1;
-            self.result.result =
-                self.result.result.pow_mod(&self.two, &self.modulus);
+            self.result.result = self.group.square(&self.result.result);
             Some(self.result.clone())
         } else {
             None
@@ -98,46 +220,17 @@ impl Iterator for VDF {
     }
 }
 
-fn calculate_and_send_proof(
-    modulus: &Int,
-    generator: &Int,
-    result: &VDFResult,
-    cap: &Int,
-    worker_sender: &Sender<Result<vdf::proof::VDFProof, vdf::InvalidCapError>>,
-) {
-    let proof = vdf::proof::VDFProof::new(
-        modulus,
-        generator,
-        result,
-        cap,
-        &vdf::proof::ProofType::Sequential,
-    )
-    .calculate();
-
-    match proof {
-        None => error!("Failed to generate a proof!"),
-        Some(success) => {
-            debug!("Proof generated! {:#?}", success);
-
-            // Send proof to caller
-            if worker_sender.send(Ok(success)).is_err() {
-                error!("Failed to send the proof to caller!");
-            }
-        }
-    }
-}
-
-impl VDF {
+impl<G: VdfGroup> VDF<G> {
     /// VDF builder with default options. Can be chained with
     /// estimate_upper_bound
     pub fn new(
-        modulus: Int,
-        generator: Int,
-        upper_bound: u32,
+        group: G,
+        generator: G::Element,
+        upper_bound: u64,
         proof_type: vdf::proof::ProofType,
     ) -> Self {
         Self {
-            modulus,
+            group,
             generator: generator.clone(),
             upper_bound,
             cap: Int::zero(),
@@ -145,74 +238,326 @@ impl VDF {
                 result: generator,
                 iterations: 0,
             },
-            two: Int::from(2),
             proof_type,
+            challenge: vdf::proof::ChallengeType::Interactive,
             proof_nudger: None,
             proof_receiver: None,
+            checkpoints: None,
+            resumed_proof_state: None,
+            window: DEFAULT_WINDOW,
+            pietrzak_spacing: vdf::pietrzak::DEFAULT_SPACING,
+            window_powers: Vec::new(),
+            cancellation: Cancellation::new(),
+        }
+    }
+
+    /// Makes the VDF produce a non-interactive proof, where the cap is derived
+    /// from the output with util::fiat_shamir_cap instead of being chosen by
+    /// a peer. Any cap received during evaluation only stops the evaluation.
+    /// Fails with a parallel proof, as the parallel prover needs to know the
+    /// cap before evaluation starts.
+    pub fn non_interactive(mut self) -> Result<Self, PoLError> {
+        if self.proof_type == vdf::proof::ProofType::Parallel {
+            return Err(PoLError::UnsupportedProofType);
+        }
+        self.challenge = vdf::proof::ChallengeType::FiatShamir;
+        Ok(self)
+    }
+
+    /// Makes the VDF produce a windowed proof, storing every window:th
+    /// intermediate value during evaluation. Larger windows need less memory
+    /// and fewer multiplications per iteration, but 2^window multiplications
+    /// more in the end.
+    pub fn with_window(mut self, window: u64) -> Self {
+        self.proof_type = vdf::proof::ProofType::Windowed;
+        self.window = window.clamp(1, vdf::proof::MAX_WINDOW);
+        self
+    }
+
+    /// Makes the VDF produce a Pietrzak proof, storing every spacing:th
+    /// intermediate value during evaluation. The midpoints of the first
+    /// halvings are put together from the stored values instead of being
+    /// squared again, smaller spacings taking more memory and less time.
+    pub fn with_pietrzak_spacing(mut self, spacing: u64) -> Self {
+        self.proof_type = vdf::proof::ProofType::Pietrzak;
+        self.pietrzak_spacing = spacing.max(1);
+        self
+    }
+
+    /// Most powers of the generator stored, enough to cover the upper bound
+    /// but never more than MAX_STORED_POWERS
+    fn max_stored_powers(&self) -> usize {
+        let interval = self.power_interval().max(1);
+        let needed = self.upper_bound / interval + 1;
+        needed.min(vdf::proof::MAX_STORED_POWERS as u64) as usize
+    }
+
+    /// How many iterations apart the stored powers of the generator are, 0
+    /// if the proof type doesn't need them
+    fn power_interval(&self) -> u64 {
+        match self.proof_type {
+            vdf::proof::ProofType::Windowed => self.window,
+            vdf::proof::ProofType::Pietrzak => self.pietrzak_spacing,
+            _ => 0,
         }
     }
 
-    /// Add a precomputed cap to the VDF
+    /// Cancels the VDF and its parallel proof calculator together with
+    /// everything else sharing the handle
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Handle cancelling the worker of this VDF
+    pub fn cancellation(&self) -> Cancellation {
+        self.cancellation.clone()
+    }
+
+    /// Reports the cancellation to the caller
+    fn send_cancelled(&self, worker_sender: &impl ProofSender<G>) {
+        debug!(
+            "VDF cancelled after {:?} iterations",
+            self.result.iterations
+        );
+        if !worker_sender.send_proof(Err(PoLError::Cancelled)) {
+            debug!("Nobody was waiting for the cancelled VDF");
+        }
+    }
+
+    /// The powers of the generator stored for a windowed or a Pietrzak proof
+    /// so far
+    pub fn window_powers(&self) -> &[G::Element] {
+        &self.window_powers
+    }
+
+    /// Add a precomputed cap to the VDF. A parallel proof is calculated
+    /// alongside the evaluation once the worker starts.
     pub fn with_cap(mut self, cap: Int) -> Self {
-        let (proof_nudger, proof_receiver): (
-            Option<Sender<bool>>,
-            Option<Receiver<vdf::proof::VDFProof>>,
-        ) = match self.proof_type {
-            vdf::proof::ProofType::Sequential => (None, None),
-            vdf::proof::ProofType::Parallel => {
-                if cap.gt(&Int::zero()) {
-                    let mut proof = vdf::proof::VDFProof::new(
-                        &self.modulus,
-                        &self.generator,
-                        &self.result,
-                        &cap,
-                        &self.proof_type,
-                    );
-                    let (nudger, receiver) = proof.calculate_parallel();
-                    (Some(nudger), Some(receiver))
-                } else {
-                    (None, None)
-                }
-            }
-        };
-        self.proof_nudger = proof_nudger;
-        self.proof_receiver = proof_receiver;
         self.cap = cap;
         self
     }
 
+    /// Starts the parallel proof calculator of a parallel proof with a
+    /// precomputed cap, continuing from the resumed state if there is one
+    fn start_parallel_proof(&mut self) {
+        if self.proof_type != vdf::proof::ProofType::Parallel
+            || self.cap <= Int::zero()
+        {
+            return;
+        }
+        let mut proof = vdf::proof::VDFProof::new(
+            &self.group,
+            &self.generator,
+            &self.result,
+            &self.cap,
+            &self.proof_type,
+        );
+        let state =
+            self.resumed_proof_state
+                .take()
+                .unwrap_or(ParallelProofState {
+                    iterations: 0,
+                    r: Int::from(1),
+                    pi: self.group.identity(),
+                });
+        let (nudger, receiver) = proof.calculate_parallel_from(
+            state,
+            self.checkpoints.clone(),
+            self.cancellation.clone(),
+        );
+        self.proof_nudger = Some(nudger);
+        self.proof_receiver = Some(receiver);
+    }
+
+    /// Persists a checkpoint of the evaluation into store every interval
+    /// iterations, and continues from the latest checkpoint already in it.
+    /// A parallel proof calculator continues from its own checkpointed state
+    /// and gets checkpointed too.
+    /// The worker refuses to start with PoLError::CheckpointMismatch if the
+    /// store was used for another group, generator or cap.
+    pub fn resume(
+        mut self,
+        store: CheckpointStore,
+        interval: u64,
+    ) -> sled::Result<Self> {
+        let with_proof_state =
+            self.proof_type == vdf::proof::ProofType::Parallel;
+        if let Some(checkpoint) = store.latest(&self.group, with_proof_state)? {
+            debug!("Resuming the VDF from iteration {}", checkpoint.iterations);
+            self.result = VDFResult {
+                result: checkpoint.result,
+                iterations: checkpoint.iterations,
+            };
+            self.resumed_proof_state = checkpoint.proof_state;
+        }
+        self.checkpoints = Some((store, interval.max(1)));
+        Ok(self)
+    }
+
+    /// Binds the checkpoint store, if any, to the statement of this VDF.
+    /// Returns false if its checkpoints were made for another statement.
+    fn checkpoints_match(&self) -> bool {
+        let store = match self.checkpoints.as_ref() {
+            Some((store, _)) => store,
+            None => return true,
+        };
+        let digest = checkpoint::statement_digest(
+            &self.group,
+            &self.generator,
+            &self.cap,
+        );
+        match store.bind(&digest) {
+            Ok(matches) => matches,
+            Err(err) => {
+                error!("Couldn't read the checkpointed statement: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Calculates the proof for the current result after the evaluation has
+    /// ended, and sends it to the caller
+    fn calculate_and_send_proof(
+        &self,
+        cap: &Int,
+        worker_sender: &impl ProofSender<G>,
+    ) {
+        // Parallel proofs end up here only without a precomputed cap
+        let proof_type = match self.proof_type {
+            vdf::proof::ProofType::Pietrzak => vdf::proof::ProofType::Pietrzak,
+            vdf::proof::ProofType::Windowed => vdf::proof::ProofType::Windowed,
+            _ => vdf::proof::ProofType::Sequential,
+        };
+        let mut proof = match self.challenge {
+            vdf::proof::ChallengeType::Interactive => {
+                vdf::proof::VDFProof::new(
+                    &self.group,
+                    &self.generator,
+                    &self.result,
+                    cap,
+                    &proof_type,
+                )
+            }
+            vdf::proof::ChallengeType::FiatShamir => {
+                vdf::proof::VDFProof::new_fiat_shamir(
+                    &self.group,
+                    &self.generator,
+                    &self.result,
+                    &proof_type,
+                )
+            }
+        };
+        let proof = match proof_type {
+            vdf::proof::ProofType::Windowed => {
+                Ok(proof.calculate_windowed(&self.window_powers, self.window))
+            }
+            vdf::proof::ProofType::Pietrzak => Ok(proof
+                .calculate_pietrzak_from_powers(
+                    &self.window_powers,
+                    self.pietrzak_spacing,
+                )),
+            _ => proof.calculate_with_cancellation(&self.cancellation),
+        };
+
+        match proof {
+            Err(_) => self.send_cancelled(worker_sender),
+            Ok(None) => error!("Failed to generate a proof!"),
+            Ok(Some(success)) => {
+                debug!("Proof generated! {:#?}", success);
+
+                // Send proof to caller
+                if !worker_sender.send_proof(Ok(success)) {
+                    error!("Failed to send the proof to caller!");
+                }
+            }
+        }
+    }
+
     /// Validates that cap is prime.
     fn validate_cap(&self, cap: &Int) -> bool {
         Verification::verify_prime(cap.clone())
     }
 
     /// Estimates the maximum number of sequential calculations that can fit in
-    /// the fiven ms_bound millisecond threshold.
-    pub fn estimate_upper_bound(mut self, ms_bound: u64) -> Self {
+    /// the fiven ms_bound millisecond threshold. Fails with the error the VDF
+    /// stopped on, if any.
+    pub fn estimate_upper_bound(
+        mut self,
+        ms_bound: u64,
+    ) -> Result<Self, PoLError> {
         let cap: Int = Generator::new_prime(128);
         let (capper, receiver) = self.clone().run_vdf_worker();
 
         let sleep_time = time::Duration::from_millis(ms_bound);
         thread::sleep(sleep_time);
-        capper.send(cap).unwrap();
+        // The worker stops on its own on an error or on reaching the upper
+        // bound first, leaving its result to tell which
+        if capper.send(cap).is_err() {
+            debug!("The VDF stopped before the cap was sent");
+        }
 
-        if let Ok(Ok(proof)) = receiver.recv() {
-            self.upper_bound = proof.output.iterations;
+        match receiver.recv() {
+            Ok(Ok(proof)) => {
+                self.upper_bound = proof.output.iterations;
+                Ok(self)
+            }
+            Ok(Err(error)) => Err(error),
+            Err(_) => Err(PoLError::ChannelClosed),
         }
-        self
     }
 
     /// A worker that does the actual calculation in a VDF. Returns a VDFProof
     /// based on initial parameters in the VDF.
-    pub fn run_vdf_worker(
+    pub fn run_vdf_worker(self) -> (Sender<Int>, Receiver<WorkerResult<G>>) {
+        let (worker_sender, caller_receiver) = unbounded();
+        (self.spawn_vdf_worker(worker_sender, None), caller_receiver)
+    }
+
+    /// Like run_vdf_worker, also reporting the progress of the evaluation
+    /// every interval. The progress channel closes when the worker ends.
+    pub fn run_vdf_worker_with_progress(
+        self,
+        interval: Duration,
+    ) -> (Sender<Int>, Receiver<WorkerResult<G>>, Receiver<Progress>) {
+        let (worker_sender, caller_receiver) = unbounded();
+        let (progress_sender, progress_receiver) = unbounded();
+        let reporter = ProgressReporter::new(
+            progress_sender,
+            interval,
+            self.result.iterations,
+        );
+        (
+            self.spawn_vdf_worker(worker_sender, Some(reporter)),
+            caller_receiver,
+            progress_receiver,
+        )
+    }
+
+    /// Like run_vdf_worker, the proof arriving on a stream that can be
+    /// awaited instead of blocked on
+    pub fn run_async_vdf_worker(
+        self,
+    ) -> (Sender<Int>, mpsc::UnboundedReceiver<WorkerResult<G>>) {
+        let (worker_sender, caller_receiver) = mpsc::unbounded();
+        (self.spawn_vdf_worker(worker_sender, None), caller_receiver)
+    }
+
+    fn spawn_vdf_worker(
         mut self,
-    ) -> (
-        Sender<Int>,
-        Receiver<Result<vdf::proof::VDFProof, vdf::InvalidCapError>>,
-    ) {
+        worker_sender: impl ProofSender<G>,
+        mut progress: Option<ProgressReporter>,
+    ) -> Sender<Int> {
         let (caller_sender, worker_receiver): (Sender<Int>, Receiver<Int>) =
             unbounded();
-        let (worker_sender, caller_receiver) = unbounded();
+
+        if !self.checkpoints_match() {
+            if !worker_sender.send_proof(Err(PoLError::CheckpointMismatch)) {
+                error!("Checkpoints belong to another VDF statement!");
+            }
+            return caller_sender;
+        }
+        self.start_parallel_proof();
 
         let timer = Instant::now();
         thread::spawn(move || loop {
@@ -228,25 +573,23 @@ impl VDF {
                     // Copy pregenerated cap
                     let mut self_cap: Int = self.cap.clone();
 
-                    // Check if default, check for primality if else
-                    if self_cap == Int::zero() {
+                    // Check if default, check for primality if else. A non
+                    // interactive proof derives its own cap.
+                    if self.challenge == vdf::proof::ChallengeType::FiatShamir {
+                        debug!("Deriving the cap from the VDF output");
+                    } else if self_cap == Int::zero() {
                         self_cap = Generator::new_safe_prime(128);
                         debug!("Cap generated: {:?}", self_cap);
                     } else if !self.validate_cap(&self_cap) {
-                        if worker_sender
-                            .send(Err(vdf::InvalidCapError))
-                            .is_err()
+                        if !worker_sender.send_proof(Err(PoLError::InvalidCap))
                         {
                             error!("Cap not correct!");
                         }
                         break;
                     }
 
-                    match self.proof_receiver {
-                        None => calculate_and_send_proof(
-                            &self.modulus,
-                            &self.generator,
-                            &self.result,
+                    match self.proof_receiver.take() {
+                        None => self.calculate_and_send_proof(
                             &self_cap,
                             &worker_sender,
                         ),
@@ -263,10 +606,13 @@ impl VDF {
                                     // Clone our result to the received proof
                                     proof.output = self.result.clone();
                                     debug!("Received proof from parallel proof calculator! {:?}", proof);
-                                    if worker_sender.send(Ok(proof)).is_err() {
+                                    if !worker_sender.send_proof(Ok(proof)) {
                                         error!("Couldn't send proof to worker listener!");
                                     }
                                 }
+                                Err(_) if self.cancellation.is_cancelled() => {
+                                    self.send_cancelled(&worker_sender)
+                                }
                                 Err(_) => {
                                     error!("Error with parallel proof calculation!");
                                 }
@@ -276,9 +622,33 @@ impl VDF {
 
                     break;
                 }
+                Some(_) if self.cancellation.is_cancelled() => {
+                    self.send_cancelled(&worker_sender);
+                    break;
+                }
                 Some(result) => {
                     self.result = result;
 
+                    if let Some(reporter) = progress.as_mut() {
+                        if !reporter.report(self.result.iterations) {
+                            progress = None;
+                        }
+                    }
+
+                    if let Some((store, interval)) = self.checkpoints.as_ref() {
+                        if self.result.iterations % interval == 0
+                            && store
+                                .save_evaluation(
+                                    &self.group,
+                                    self.result.iterations,
+                                    &self.result.result,
+                                )
+                                .is_err()
+                        {
+                            error!("Couldn't checkpoint the VDF evaluation!");
+                        }
+                    }
+
                     if let Some(nudger) = self.proof_nudger.as_ref() {
                         if nudger.try_send(true).is_err() {
                             error!("Couldn't nudge the parallel proof!")
@@ -291,13 +661,14 @@ impl VDF {
                         // Cap received
                         debug!("Received the cap {:?} after {:?} milliseconds, generating proof.", cap, timer.elapsed().as_millis());
 
-                        // Check for primality
-                        if self.validate_cap(&cap) {
-                            match self.proof_receiver {
-                                None => calculate_and_send_proof(
-                                    &self.modulus,
-                                    &self.generator,
-                                    &self.result,
+                        // Check for primality, a non-interactive VDF uses the
+                        // cap only as a stop signal
+                        if self.challenge
+                            == vdf::proof::ChallengeType::FiatShamir
+                            || self.validate_cap(&cap)
+                        {
+                            match self.proof_receiver.take() {
+                                None => self.calculate_and_send_proof(
                                     &cap,
                                     &worker_sender,
                                 ),
@@ -307,13 +678,16 @@ impl VDF {
                                         // proof
                                         proof.output = self.result.clone();
                                         debug!("Received proof from parallel proof calculator! {:?}", proof);
-                                        if worker_sender
-                                            .send(Ok(proof))
-                                            .is_err()
+                                        if !worker_sender.send_proof(Ok(proof))
                                         {
                                             error!("Couldn't send proof to worker listener!");
                                         }
                                     }
+                                    Err(_)
+                                        if self.cancellation.is_cancelled() =>
+                                    {
+                                        self.send_cancelled(&worker_sender)
+                                    }
                                     Err(_) => {
                                         error!("Error with parallel proof calculation!");
                                     }
@@ -323,13 +697,10 @@ impl VDF {
                             error!("Received cap was not a prime!");
                             // Received cap was not a prime, send error to
                             // caller
-                            if worker_sender
-                                .send(Err(vdf::InvalidCapError))
-                                .is_err()
+                            if !worker_sender
+                                .send_proof(Err(PoLError::InvalidCap))
                             {
-                                error!(
-                                    "Error sending InvalidCapError to caller!"
-                                );
+                                error!("Error sending InvalidCap to caller!");
                             }
                         }
                         break;
@@ -340,6 +711,37 @@ impl VDF {
             }
         });
 
-        (caller_sender, caller_receiver)
+        caller_sender
+    }
+}
+
+/// Where a VDF worker sends its proof
+trait ProofSender<G: VdfGroup>: Send + 'static {
+    /// Returns false if the caller is gone
+    fn send_proof(
+        &self,
+        proof: Result<vdf::proof::VDFProof<G>, PoLError>,
+    ) -> bool;
+}
+
+impl<G: VdfGroup> ProofSender<G>
+    for Sender<Result<vdf::proof::VDFProof<G>, PoLError>>
+{
+    fn send_proof(
+        &self,
+        proof: Result<vdf::proof::VDFProof<G>, PoLError>,
+    ) -> bool {
+        self.send(proof).is_ok()
+    }
+}
+
+impl<G: VdfGroup> ProofSender<G>
+    for mpsc::UnboundedSender<Result<vdf::proof::VDFProof<G>, PoLError>>
+{
+    fn send_proof(
+        &self,
+        proof: Result<vdf::proof::VDFProof<G>, PoLError>,
+    ) -> bool {
+        self.unbounded_send(proof).is_ok()
     }
 }
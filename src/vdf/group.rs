@@ -0,0 +1,221 @@
+use crate::vdf::bigint::Int;
+use crate::vdf::util;
+use std::fmt::Debug;
+
+/// The operations a VDF needs from the group it is evaluated in. The evaluator
+/// and the provers only ever touch group elements through this trait, so any
+/// group of unknown order can be plugged in.
+pub trait VdfGroup: Clone + Debug + PartialEq + Send + Sync + 'static {
+    type Element: Clone + Debug + Default + PartialEq + Eq + Send + Sync;
+
+    /// The neutral element
+    fn identity(&self) -> Self::Element;
+
+    /// Squares the element, the sequential step of the VDF
+    fn square(&self, x: &Self::Element) -> Self::Element;
+
+    fn multiply(&self, a: &Self::Element, b: &Self::Element) -> Self::Element;
+
+    /// Raises the element to a non-negative power
+    fn exponentiate(
+        &self,
+        base: &Self::Element,
+        exponent: &Int,
+    ) -> Self::Element {
+        let mut result = self.identity();
+        for i in (0..exponent.bit_length()).rev() {
+            result = self.square(&result);
+            if exponent.bit(i) {
+                result = self.multiply(&result, base);
+            }
+        }
+        result
+    }
+
+    /// Canonical string encoding of an element, used on the wire and when
+    /// hashing statements
+    fn encode(&self, x: &Self::Element) -> String;
+
+    fn decode(&self, s: &str) -> Option<Self::Element>;
+
+    /// Checks that a value received from someone else is a member of the group
+    fn is_element(&self, x: &Self::Element) -> bool;
+
+    /// Deterministically hashes the input string s to a member of the group
+    fn hash_to_element(&self, s: &str) -> Self::Element;
+
+    /// Canonical string encoding of the group parameters
+    fn encode_group(&self) -> String;
+
+    fn decode_group(s: &str) -> Option<Self>;
+
+    /// Size of the group parameters in bits, which the cost of a squaring
+    /// depends on
+    fn bit_length(&self) -> u32;
+}
+
+/// The multiplicative group of integers modulo an RSA modulus N
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RsaGroup {
+    pub modulus: Int,
+}
+
+impl RsaGroup {
+    pub fn new(modulus: Int) -> Self {
+        Self { modulus }
+    }
+}
+
+impl VdfGroup for RsaGroup {
+    type Element = Int;
+
+    fn identity(&self) -> Int {
+        Int::one()
+    }
+
+    fn square(&self, x: &Int) -> Int {
+        x.pow_mod(&Int::from(2), &self.modulus)
+    }
+
+    fn multiply(&self, a: &Int, b: &Int) -> Int {
+        a * b % &self.modulus
+    }
+
+    fn exponentiate(&self, base: &Int, exponent: &Int) -> Int {
+        base.pow_mod(exponent, &self.modulus)
+    }
+
+    fn encode(&self, x: &Int) -> String {
+        x.to_str_radix(10, false)
+    }
+
+    fn decode(&self, s: &str) -> Option<Int> {
+        Int::from_str_radix(s, 10).ok()
+    }
+
+    fn is_element(&self, x: &Int) -> bool {
+        *x > Int::zero() && *x < self.modulus
+    }
+
+    fn hash_to_element(&self, s: &str) -> Int {
+        util::hash_to_mod(s, &self.modulus)
+    }
+
+    fn encode_group(&self) -> String {
+        self.modulus.to_str_radix(10, false)
+    }
+
+    fn bit_length(&self) -> u32 {
+        self.modulus.bit_length()
+    }
+
+    fn decode_group(s: &str) -> Option<Self> {
+        let modulus = Int::from_str_radix(s, 10).ok()?;
+        if modulus > Int::one() {
+            Some(Self::new(modulus))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::evaluation::VDF;
+    use crate::vdf::proof::{ProofType, VDFProof};
+
+    /// Integers modulo a modulus small enough for u64 arithmetic, with
+    /// factors everyone knows. Only good for testing the generic code.
+    #[derive(Debug, Clone, PartialEq)]
+    struct ToyGroup {
+        modulus: u64,
+    }
+
+    impl VdfGroup for ToyGroup {
+        type Element = u64;
+
+        fn identity(&self) -> u64 {
+            1
+        }
+
+        fn square(&self, x: &u64) -> u64 {
+            self.multiply(x, x)
+        }
+
+        fn multiply(&self, a: &u64, b: &u64) -> u64 {
+            (u128::from(*a) * u128::from(*b) % u128::from(self.modulus)) as u64
+        }
+
+        fn encode(&self, x: &u64) -> String {
+            x.to_string()
+        }
+
+        fn decode(&self, s: &str) -> Option<u64> {
+            s.parse().ok()
+        }
+
+        fn is_element(&self, x: &u64) -> bool {
+            *x > 0 && *x < self.modulus
+        }
+
+        fn hash_to_element(&self, s: &str) -> u64 {
+            let hash = util::hash_to_mod(s, &Int::from(self.modulus));
+            hash.to_str_radix(10, false).parse().unwrap()
+        }
+
+        fn encode_group(&self) -> String {
+            self.modulus.to_string()
+        }
+
+        fn decode_group(s: &str) -> Option<Self> {
+            let modulus = s.parse().ok().filter(|modulus| *modulus > 1)?;
+            Some(Self { modulus })
+        }
+
+        fn bit_length(&self) -> u32 {
+            64 - self.modulus.leading_zeros()
+        }
+    }
+
+    fn toy_group() -> ToyGroup {
+        ToyGroup {
+            modulus: 1_000_003 * 1_000_033,
+        }
+    }
+
+    #[test]
+    fn vdf_runs_over_a_toy_group() {
+        let group = toy_group();
+        let generator = group.hash_to_element("toy");
+
+        for proof_type in [ProofType::Sequential, ProofType::Pietrzak].iter() {
+            let vdf =
+                VDF::new(group.clone(), generator, 200, proof_type.clone());
+            let (_, receiver) = vdf.run_vdf_worker();
+            let proof: VDFProof<ToyGroup> = receiver.recv().unwrap().unwrap();
+
+            let expected = (0..200).fold(generator, |x, _| group.square(&x));
+            assert_eq!(proof.output.result, expected);
+            assert!(proof.verify());
+            assert!(proof.deserialize().verify::<ToyGroup>());
+
+            let mut forged = proof.clone();
+            forged.output.result = group.square(&forged.output.result);
+            assert!(!forged.verify());
+        }
+    }
+
+    #[test]
+    fn toy_group_round_trips_through_its_encoding() {
+        let group = toy_group();
+        let x = group.hash_to_element("round trip");
+        assert!(group.is_element(&x));
+        assert_eq!(group.decode(&group.encode(&x)), Some(x));
+        assert_eq!(
+            group.exponentiate(&x, &Int::from(5)),
+            (0..5).fold(1, |y, _| group.multiply(&y, &x))
+        );
+        assert_eq!(ToyGroup::decode_group(&group.encode_group()), Some(group));
+    }
+}
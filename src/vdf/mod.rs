@@ -1,35 +1,22 @@
 #![allow(soft_unstable)]
-use std::error::Error;
-use std::fmt;
-
+pub mod batch;
+pub mod bigint;
+pub mod calibration;
+pub mod checkpoint;
+pub mod class_group;
 pub mod evaluation;
+pub mod group;
+pub mod pietrzak;
 pub mod proof;
 pub mod util;
 
-/// InvalidCapError is returned when a non-prime cap is received in the
-/// vdf_worker
-#[derive(Debug)]
-pub struct InvalidCapError;
-
-impl fmt::Display for InvalidCapError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Invalid cap value encountered!")
-    }
-}
-
-impl Error for InvalidCapError {
-    fn description(&self) -> &str {
-        "Invalid cap value encountered!"
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ramp::Int;
-    use ramp_primes::Generator;
+    use crate::error::PoLError;
+    use crate::vdf::bigint::Generator;
+    use crate::vdf::bigint::Int;
     use std::{thread, time};
-    use test::Bencher;
 
     const RSA_2048: &str = "2519590847565789349402718324004839857142928212620403202777713783604366202070759555626401852588078440691829064124951508218929855914917618450280848912007284499268739280728777673597141834727026189637501497182469116507761337985909570009733045974880842840179742910064245869181719511874612151517265463228221686998754918242243363725908514186546204357679842338718477444792073993423658482382428119816381501067481045166037730605620161967625613384414360383390441495263443219011465754445417842402092461651572335077870774981712577246796292638635637328991215483143816789988504044536402352738195137863656439121201039712282120720357";
 
@@ -43,14 +30,14 @@ mod tests {
         // result
         let cap = Generator::new_safe_prime(128);
         let verifiers_vdf = evaluation::VDF::new(
-            modulus.clone(),
+            group::RsaGroup::new(modulus.clone()),
             root_hashed.clone(),
             256,
             proof::ProofType::Sequential,
         )
         .with_cap(cap.clone());
         let provers_vdf = evaluation::VDF::new(
-            modulus,
+            group::RsaGroup::new(modulus),
             root_hashed,
             256,
             proof::ProofType::Parallel,
@@ -82,9 +69,9 @@ mod tests {
         let two = Int::from(2);
         let cap = Int::from(7);
         let mut vdf = evaluation::VDF::new(
-            modulus,
+            group::RsaGroup::new(modulus),
             generator,
-            u32::MAX,
+            u64::MAX,
             proof::ProofType::Sequential,
         );
 
@@ -98,7 +85,7 @@ mod tests {
         assert_eq!(vdf.result.result, Int::from(16));
 
         let proof = proof::VDFProof::new(
-            &vdf.modulus,
+            &vdf.group,
             &vdf.generator,
             &vdf.result,
             &cap,
@@ -120,9 +107,9 @@ mod tests {
 
         let cap = Generator::new_safe_prime(16);
         let vdf = evaluation::VDF::new(
-            modulus.clone(),
+            group::RsaGroup::new(modulus.clone()),
             root_hashed.clone(),
-            u32::MAX,
+            u64::MAX,
             proof::ProofType::Sequential,
         );
 
@@ -141,7 +128,7 @@ mod tests {
         }
 
         let vdf2 = evaluation::VDF::new(
-            modulus,
+            group::RsaGroup::new(modulus),
             root_hashed,
             first_proof.output.iterations,
             proof::ProofType::Sequential,
@@ -157,6 +144,429 @@ mod tests {
         }
     }
 
+    #[test]
+    fn non_interactive_proof_is_publicly_verifiable() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("fiat-shamir", &modulus);
+
+        let vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            root_hashed,
+            128,
+            proof::ProofType::Sequential,
+        )
+        .non_interactive()
+        .unwrap();
+
+        let (_, receiver) = vdf.run_vdf_worker();
+
+        if let Ok(Ok(proof)) = receiver.recv() {
+            assert_eq!(proof.challenge, proof::ChallengeType::FiatShamir);
+            assert!(proof.verify_non_interactive());
+            assert!(proof
+                .deserialize()
+                .verify_non_interactive::<group::RsaGroup>());
+
+            // A proof with a cap of the prover's choosing must be rejected
+            let mut forged = proof;
+            forged.cap = Generator::new_safe_prime(128);
+            forged.pi = Int::zero();
+            let mut forged = forged.calculate().unwrap();
+            assert!(!forged.verify_non_interactive());
+
+            // Also when the proof claims the cap was chosen interactively
+            forged.challenge = proof::ChallengeType::Interactive;
+            assert!(forged.verify());
+            assert!(!forged.verify_non_interactive());
+        } else {
+            panic!("The VDF worker didn't return a proof");
+        }
+    }
+
+    #[test]
+    fn parallel_proof_cant_be_non_interactive() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            Int::from(2),
+            128,
+            proof::ProofType::Parallel,
+        );
+        assert_eq!(
+            vdf.non_interactive().err(),
+            Some(PoLError::UnsupportedProofType)
+        );
+    }
+
+    #[test]
+    fn cancelled_vdf_reports_the_cancellation() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("cancel", &modulus);
+
+        let vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            root_hashed,
+            u64::MAX,
+            proof::ProofType::Sequential,
+        );
+        let cancellation = vdf.cancellation();
+        let (_capper, receiver) = vdf.run_vdf_worker();
+
+        thread::sleep(time::Duration::from_millis(20));
+        cancellation.cancel();
+        assert_eq!(
+            receiver.recv_timeout(time::Duration::from_secs(5)).unwrap(),
+            Err(PoLError::Cancelled)
+        );
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn cancellation_stops_the_parallel_proof() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("cancel parallel", &modulus);
+        let cancellation = evaluation::Cancellation::new();
+
+        let vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            root_hashed,
+            u64::MAX,
+            proof::ProofType::Parallel,
+        )
+        .with_cancellation(cancellation.clone())
+        .with_cap(Generator::new_safe_prime(128));
+        let (_capper, receiver) = vdf.run_vdf_worker();
+
+        thread::sleep(time::Duration::from_millis(50));
+        cancellation.cancel();
+        assert_eq!(
+            receiver.recv_timeout(time::Duration::from_secs(5)).unwrap(),
+            Err(PoLError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn parallel_proof_is_cancelled_whatever_the_builder_order() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("cancel parallel", &modulus);
+        let cancellation = evaluation::Cancellation::new();
+
+        let vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            root_hashed,
+            u64::MAX,
+            proof::ProofType::Parallel,
+        )
+        .with_cap(Generator::new_safe_prime(128))
+        .with_cancellation(cancellation.clone());
+        let (_capper, receiver) = vdf.run_vdf_worker();
+
+        thread::sleep(time::Duration::from_millis(50));
+        cancellation.cancel();
+        assert_eq!(
+            receiver.recv_timeout(time::Duration::from_secs(5)).unwrap(),
+            Err(PoLError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn cancelled_sequential_proof_stops() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let group = group::RsaGroup::new(modulus.clone());
+        let root_hashed = util::hash_to_mod("cancel proof", &modulus);
+        let result = evaluation::VDFResult {
+            result: root_hashed.clone(),
+            iterations: u64::MAX,
+        };
+        let cancellation = evaluation::Cancellation::new();
+        cancellation.cancel();
+
+        let mut proof = proof::VDFProof::new(
+            &group,
+            &root_hashed,
+            &result,
+            &Generator::new_safe_prime(128),
+            &proof::ProofType::Sequential,
+        );
+        assert_eq!(
+            proof.calculate_with_cancellation(&cancellation),
+            Err(PoLError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn worker_reports_its_progress() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("progress", &modulus);
+
+        let vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            root_hashed,
+            u64::MAX,
+            proof::ProofType::Sequential,
+        );
+        let cancellation = vdf.cancellation();
+        let (_capper, _receiver, progress) =
+            vdf.run_vdf_worker_with_progress(time::Duration::from_millis(10));
+
+        let first = progress.recv().unwrap();
+        let second = progress.recv().unwrap();
+        assert!(first.iterations > 0);
+        assert!(second.iterations > first.iterations);
+        assert!(second.elapsed >= first.elapsed);
+        assert!(second.squarings_per_second > 0.0);
+
+        // The reports end with the worker
+        cancellation.cancel();
+        while progress.recv().is_ok() {}
+    }
+
+    #[test]
+    fn pietrzak_proof_is_calculated_by_the_worker() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("pietrzak", &modulus);
+
+        let vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            root_hashed,
+            300,
+            proof::ProofType::Pietrzak,
+        )
+        .with_pietrzak_spacing(4);
+
+        let (_, receiver) = vdf.run_vdf_worker();
+
+        if let Ok(Ok(proof)) = receiver.recv() {
+            assert_eq!(proof.proof_type, proof::ProofType::Pietrzak);
+            assert!(!proof.mu.is_empty());
+            assert!(proof.verify());
+            assert!(proof.deserialize().verify::<group::RsaGroup>());
+        } else {
+            panic!("The VDF worker didn't return a proof");
+        }
+    }
+
+    #[test]
+    fn malformed_proof_is_an_error() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("malformed", &modulus);
+        let result = evaluation::VDF::new(
+            group::RsaGroup::new(modulus.clone()),
+            root_hashed.clone(),
+            16,
+            proof::ProofType::Sequential,
+        )
+        .last()
+        .unwrap();
+        let valid = proof::VDFProof::new(
+            &group::RsaGroup::new(modulus),
+            &root_hashed,
+            &result,
+            &Generator::new_safe_prime(128),
+            &proof::ProofType::Sequential,
+        )
+        .calculate()
+        .unwrap()
+        .deserialize();
+
+        let mut malformed = valid.clone();
+        malformed.cap = String::from("0x1f");
+        assert!(matches!(
+            malformed.serialize::<group::RsaGroup>(),
+            Err(PoLError::Parse { .. })
+        ));
+
+        let mut malformed = valid;
+        malformed.pi = String::from("pi");
+        assert_eq!(
+            malformed.serialize::<group::RsaGroup>(),
+            Err(PoLError::InvalidGroupElement)
+        );
+        assert!(!malformed.verify::<group::RsaGroup>());
+    }
+
+    #[test]
+    fn resumed_vdf_produces_a_valid_proof() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let group = group::RsaGroup::new(modulus);
+        let root_hashed = util::hash_to_mod("checkpoints", &group.modulus);
+        let cap = Generator::new_safe_prime(128);
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = checkpoint::CheckpointStore::from_db(&db, "test").unwrap();
+
+        // The first run stops at 60 iterations, as if it was interrupted
+        let (_, receiver) = evaluation::VDF::new(
+            group.clone(),
+            root_hashed.clone(),
+            60,
+            proof::ProofType::Parallel,
+        )
+        .resume(store.clone(), 20)
+        .unwrap()
+        .with_cap(cap.clone())
+        .run_vdf_worker();
+        assert!(receiver.recv().unwrap().unwrap().verify());
+
+        let checkpoint = store.latest(&group, true).unwrap().unwrap();
+        assert_eq!(checkpoint.iterations, 60);
+
+        let (_, receiver) = evaluation::VDF::new(
+            group.clone(),
+            root_hashed.clone(),
+            150,
+            proof::ProofType::Parallel,
+        )
+        .resume(store.clone(), 20)
+        .unwrap()
+        .with_cap(cap.clone())
+        .run_vdf_worker();
+        let resumed = receiver.recv().unwrap().unwrap();
+        assert_eq!(resumed.output.iterations, 150);
+        assert!(resumed.verify());
+
+        let expected = evaluation::VDF::new(
+            group.clone(),
+            root_hashed.clone(),
+            150,
+            proof::ProofType::Sequential,
+        )
+        .last()
+        .unwrap();
+        assert_eq!(resumed.output, expected);
+
+        // Checkpoints of another generator or cap are never resumed from
+        for (generator, cap) in &[
+            (util::hash_to_mod("other", &group.modulus), cap.clone()),
+            (root_hashed.clone(), Generator::new_safe_prime(128)),
+        ] {
+            let (_, receiver) = evaluation::VDF::new(
+                group.clone(),
+                generator.clone(),
+                200,
+                proof::ProofType::Parallel,
+            )
+            .resume(store.clone(), 20)
+            .unwrap()
+            .with_cap(cap.clone())
+            .run_vdf_worker();
+            assert_eq!(
+                receiver.recv().unwrap(),
+                Err(PoLError::CheckpointMismatch)
+            );
+        }
+    }
+
+    #[test]
+    fn upper_bound_estimate_returns_why_the_vdf_stopped() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let group = group::RsaGroup::new(modulus);
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = checkpoint::CheckpointStore::from_db(&db, "test").unwrap();
+        let vdf = |generator: &str, upper_bound| {
+            evaluation::VDF::new(
+                group.clone(),
+                util::hash_to_mod(generator, &group.modulus),
+                upper_bound,
+                proof::ProofType::Sequential,
+            )
+        };
+
+        // Reaching the upper bound before the cap is an estimate too
+        let estimated = vdf("estimate", 20)
+            .resume(store.clone(), 10)
+            .unwrap()
+            .estimate_upper_bound(200)
+            .unwrap();
+        assert_eq!(estimated.upper_bound, 20);
+
+        assert_eq!(
+            vdf("other", 20)
+                .resume(store, 10)
+                .unwrap()
+                .estimate_upper_bound(10)
+                .err(),
+            Some(PoLError::CheckpointMismatch)
+        );
+    }
+
+    #[test]
+    fn stored_powers_are_bounded() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let mut vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            Int::from(2),
+            40,
+            proof::ProofType::Sequential,
+        )
+        .with_window(40);
+        assert_eq!(vdf.by_ref().count(), 40);
+        // The window is cut to MAX_WINDOW, storing the powers 0 and 16 and 32
+        assert_eq!(vdf.window_powers().len(), 3);
+    }
+
+    #[test]
+    fn windowed_proof_equals_sequential_proof() {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let group = group::RsaGroup::new(modulus);
+        let root_hashed = util::hash_to_mod("windowed", &group.modulus);
+        let cap = Generator::new_safe_prime(128);
+
+        for (iterations, window) in &[(1, 4), (3, 4), (64, 4), (203, 5)] {
+            let mut vdf = evaluation::VDF::new(
+                group.clone(),
+                root_hashed.clone(),
+                *iterations,
+                proof::ProofType::Sequential,
+            )
+            .with_window(*window);
+            let result = vdf.by_ref().last().unwrap();
+
+            let mut windowed = proof::VDFProof::new(
+                &group,
+                &root_hashed,
+                &result,
+                &cap,
+                &proof::ProofType::Windowed,
+            );
+            let windowed = windowed
+                .calculate_windowed(vdf.window_powers(), *window)
+                .unwrap();
+            let sequential = proof::VDFProof::new(
+                &group,
+                &root_hashed,
+                &result,
+                &cap,
+                &proof::ProofType::Sequential,
+            )
+            .calculate()
+            .unwrap();
+
+            assert_eq!(windowed.pi, sequential.pi);
+            assert!(windowed.verify());
+        }
+
+        let (_, receiver) = evaluation::VDF::new(
+            group,
+            root_hashed,
+            100,
+            proof::ProofType::Sequential,
+        )
+        .with_window(3)
+        .with_cap(cap)
+        .run_vdf_worker();
+        let proof = receiver.recv().unwrap().unwrap();
+        assert_eq!(proof.proof_type, proof::ProofType::Windowed);
+        assert!(proof.verify());
+    }
+}
+
+#[cfg(all(test, feature = "nightly"))]
+mod benches {
+    use super::*;
+    use crate::vdf::bigint::{Generator, Int};
+    use crate::RSA_2048;
+    use test::Bencher;
+
     #[bench]
     fn bench_sequential(b: &mut Bencher) {
         let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
@@ -167,7 +577,7 @@ mod tests {
         b.iter(|| {
             let cap = Int::from_str_radix(&cap_str, 10).unwrap();
             let vdf = evaluation::VDF::new(
-                modulus.clone(),
+                group::RsaGroup::new(modulus.clone()),
                 root_hashed.clone(),
                 256,
                 proof::ProofType::Sequential,
@@ -192,7 +602,7 @@ mod tests {
         b.iter(|| {
             let cap = Int::from_str_radix(&cap_str, 10).unwrap();
             let vdf = evaluation::VDF::new(
-                modulus.clone(),
+                group::RsaGroup::new(modulus.clone()),
                 root_hashed.clone(),
                 256,
                 proof::ProofType::Parallel,
@@ -207,4 +617,128 @@ mod tests {
             }
         })
     }
+
+    /// Evaluates a VDF once, so that the proof benchmarks below calculate
+    /// their proofs for the same VDFResult
+    fn bench_result(
+        iterations: u64,
+    ) -> (group::RsaGroup, Int, evaluation::VDFResult) {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("proof benchmarks", &modulus);
+        let result = evaluation::VDF::new(
+            group::RsaGroup::new(modulus.clone()),
+            root_hashed.clone(),
+            iterations,
+            proof::ProofType::Sequential,
+        )
+        .last()
+        .unwrap();
+        (group::RsaGroup::new(modulus), root_hashed, result)
+    }
+
+    #[bench]
+    fn bench_wesolowski_proof(b: &mut Bencher) {
+        let (group, generator, result) = bench_result(4096);
+        let cap = Generator::new_safe_prime(128);
+        b.iter(|| {
+            proof::VDFProof::new(
+                &group,
+                &generator,
+                &result,
+                &cap,
+                &proof::ProofType::Sequential,
+            )
+            .calculate()
+        })
+    }
+
+    #[bench]
+    fn bench_windowed_proof(b: &mut Bencher) {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let root_hashed = util::hash_to_mod("proof benchmarks", &modulus);
+        let mut vdf = evaluation::VDF::new(
+            group::RsaGroup::new(modulus),
+            root_hashed,
+            4096,
+            proof::ProofType::Sequential,
+        )
+        .with_window(evaluation::DEFAULT_WINDOW);
+        let result = vdf.by_ref().last().unwrap();
+        let cap = Generator::new_safe_prime(128);
+        b.iter(|| {
+            proof::VDFProof::new(
+                &vdf.group,
+                &vdf.generator,
+                &result,
+                &cap,
+                &proof::ProofType::Windowed,
+            )
+            .calculate_windowed(vdf.window_powers(), evaluation::DEFAULT_WINDOW)
+        })
+    }
+
+    #[bench]
+    fn bench_pietrzak_proof(b: &mut Bencher) {
+        let (group, generator, result) = bench_result(4096);
+        b.iter(|| {
+            proof::VDFProof::new(
+                &group,
+                &generator,
+                &result,
+                &Int::zero(),
+                &proof::ProofType::Pietrzak,
+            )
+            .calculate()
+        })
+    }
+
+    /// Wesolowski proofs of different generators in the same group
+    fn bench_proofs(count: usize) -> Vec<proof::DeserializableVDFProof> {
+        let modulus = Int::from_str_radix(RSA_2048, 10).unwrap();
+        let group = group::RsaGroup::new(modulus);
+        let cap = Generator::new_safe_prime(128);
+        (0..count)
+            .map(|i| {
+                let generator = util::hash_to_mod(
+                    &format!("batch benchmarks {}", i),
+                    &group.modulus,
+                );
+                let result = evaluation::VDF::new(
+                    group.clone(),
+                    generator.clone(),
+                    1024,
+                    proof::ProofType::Sequential,
+                )
+                .last()
+                .unwrap();
+                proof::VDFProof::new(
+                    &group,
+                    &generator,
+                    &result,
+                    &cap,
+                    &proof::ProofType::Sequential,
+                )
+                .calculate()
+                .unwrap()
+                .deserialize()
+            })
+            .collect()
+    }
+
+    #[bench]
+    fn bench_verify_one_by_one(b: &mut Bencher) {
+        let proofs = bench_proofs(64);
+        b.iter(|| {
+            proofs
+                .iter()
+                .filter(|proof| !proof.verify::<group::RsaGroup>())
+                .count()
+        })
+    }
+
+    #[bench]
+    fn bench_verify_batch(b: &mut Bencher) {
+        let proofs = bench_proofs(64);
+        b.iter(|| batch::verify_batch::<group::RsaGroup>(&proofs))
+    }
 }
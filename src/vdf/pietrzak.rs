@@ -0,0 +1,289 @@
+use crate::vdf::bigint::Int;
+use crate::vdf::evaluation::VDFResult;
+use crate::vdf::group::VdfGroup;
+use crate::vdf::util;
+
+/// Bit length of the random exponents used when halving the statement
+const CHALLENGE_BITS: usize = 128;
+
+/// Spacing of the powers of the generator stored during evaluation for a
+/// Pietrzak proof, unless set with VDF::with_pietrzak_spacing
+pub const DEFAULT_SPACING: u64 = 1024;
+
+/// Canonical encoding of the whole statement generator^(2^T) = result, which
+/// every challenge is bound to
+fn statement<G: VdfGroup>(
+    group: &G,
+    generator: &G::Element,
+    output: &VDFResult<G::Element>,
+) -> String {
+    format!(
+        "{}|{}|{}|{}",
+        group.encode_group(),
+        group.encode(generator),
+        output.iterations,
+        group.encode(&output.result)
+    )
+}
+
+/// Derives the halving challenge r from the whole statement, the current
+/// statement x^(2^t) = y and the midpoint mu
+fn challenge<G: VdfGroup>(
+    group: &G,
+    statement: &str,
+    t: u64,
+    x: &G::Element,
+    y: &G::Element,
+    mu: &G::Element,
+) -> Int {
+    let round = format!(
+        "{}|{}|{}|{}|{}",
+        statement,
+        t,
+        group.encode(x),
+        group.encode(y),
+        group.encode(mu)
+    );
+    util::hash_to_int(&round, CHALLENGE_BITS as u32)
+        % (Int::one() << CHALLENGE_BITS)
+}
+
+/// Squares x t times in the group
+fn square_times<G: VdfGroup>(group: &G, x: &G::Element, t: u64) -> G::Element {
+    let mut result = x.clone();
+    for _ in 0..t {
+        result = group.square(&result);
+    }
+    result
+}
+
+/// Halves the statement x^(2^(2t)) = y with the midpoint mu = x^(2^t),
+/// returning the new (x, y) and the challenge used
+fn halve<G: VdfGroup>(
+    group: &G,
+    statement: &str,
+    t: u64,
+    x: &G::Element,
+    y: &G::Element,
+    mu: &G::Element,
+) -> (G::Element, G::Element, Int) {
+    let r = challenge(group, statement, t, x, y, mu);
+    let x_next = group.multiply(&group.exponentiate(x, &r), mu);
+    let y_next = group.multiply(&group.exponentiate(mu, &r), y);
+    (x_next, y_next, r)
+}
+
+/// The powers generator^(2^(spacing*i)) stored during evaluation, from which
+/// generator^(2^position) takes less than spacing squarings
+struct Powers<'a, G: VdfGroup> {
+    group: &'a G,
+    powers: &'a [G::Element],
+    spacing: u64,
+    output: &'a VDFResult<G::Element>,
+}
+
+impl<'a, G: VdfGroup> Powers<'a, G> {
+    fn power(&self, position: u64) -> G::Element {
+        // Odd halvings square the output, so positions may lie beyond it
+        if position >= self.output.iterations {
+            return square_times(
+                self.group,
+                &self.output.result,
+                position - self.output.iterations,
+            );
+        }
+        let index = (position / self.spacing) as usize;
+        square_times(self.group, &self.powers[index], position % self.spacing)
+    }
+}
+
+/// Calculates a Pietrzak proof for generator^(2^iterations) = result, every
+/// midpoint taking t/2 squarings of the current statement
+pub fn prove<G: VdfGroup>(
+    group: &G,
+    generator: &G::Element,
+    output: &VDFResult<G::Element>,
+) -> Vec<G::Element> {
+    prove_from_powers(group, generator, output, &[], 0)
+}
+
+/// Calculates a Pietrzak proof for generator^(2^iterations) = result. The
+/// proof consists of the midpoints of each halving round, log2(T) group
+/// elements in total. An odd T is made even by squaring both the output and
+/// the iteration count, x^(2^(T+1)) = y^2.
+///
+/// The current x is a product of powers generator^(2^p), raised to products
+/// of the challenges so far. As long as there are few of them, the midpoint
+/// is put together from the powers stored every spacing iterations during
+/// evaluation instead of squaring x t/2 times. Without enough stored powers
+/// every midpoint is squared.
+pub fn prove_from_powers<G: VdfGroup>(
+    group: &G,
+    generator: &G::Element,
+    output: &VDFResult<G::Element>,
+    powers: &[G::Element],
+    spacing: u64,
+) -> Vec<G::Element> {
+    let statement = statement(group, generator, output);
+    let covered = spacing > 0
+        && output.iterations > 0
+        && powers.len() as u64 > (output.iterations - 1) / spacing;
+    let stored = Powers {
+        group,
+        powers,
+        spacing,
+        output,
+    };
+    // x as the positions p and exponents e of its factors generator^(2^p)^e
+    let mut factors: Vec<(u64, Int)> = vec![(0, Int::one())];
+    let mut use_powers = covered;
+
+    let mut x = generator.clone();
+    let mut y = output.result.clone();
+    let mut t = output.iterations;
+    let mut mu_list = Vec::new();
+
+    while t > 1 {
+        if t % 2 == 1 {
+            y = group.square(&y);
+            t += 1;
+        }
+        t /= 2;
+
+        // Every factor costs up to spacing squarings and an exponentiation
+        // by the product of the challenges so far
+        let rounds = mu_list.len() as u64 + 1;
+        let cost =
+            factors.len() as u64 * (spacing + CHALLENGE_BITS as u64 * rounds);
+        use_powers = use_powers && cost < t;
+        let mu = if use_powers {
+            factors.iter().fold(group.identity(), |mu, (position, e)| {
+                let power = stored.power(position + t);
+                group.multiply(&mu, &group.exponentiate(&power, e))
+            })
+        } else {
+            square_times(group, &x, t)
+        };
+
+        let (x_next, y_next, r) = halve(group, &statement, t, &x, &y, &mu);
+        if use_powers {
+            let shifted: Vec<(u64, Int)> = factors
+                .iter()
+                .map(|(position, e)| (position + t, e.clone()))
+                .collect();
+            for (_, e) in factors.iter_mut() {
+                *e = &*e * &r;
+            }
+            factors.extend(shifted);
+        }
+        x = x_next;
+        y = y_next;
+        mu_list.push(mu);
+    }
+
+    mu_list
+}
+
+/// Verifies a Pietrzak proof by repeating the halving rounds of the prover and
+/// checking the final single squaring
+pub fn verify<G: VdfGroup>(
+    group: &G,
+    generator: &G::Element,
+    output: &VDFResult<G::Element>,
+    mu_list: &[G::Element],
+) -> bool {
+    let statement = statement(group, generator, output);
+    let mut x = generator.clone();
+    let mut y = output.result.clone();
+    let mut t = output.iterations;
+    let mut midpoints = mu_list.iter();
+
+    while t > 1 {
+        if t % 2 == 1 {
+            y = group.square(&y);
+            t += 1;
+        }
+        t /= 2;
+        let mu = match midpoints.next() {
+            Some(mu) => mu,
+            None => return false,
+        };
+        // Check that the midpoint belongs in the group
+        if !group.is_element(mu) {
+            return false;
+        }
+        let (x_next, y_next, _) = halve(group, &statement, t, &x, &y, mu);
+        x = x_next;
+        y = y_next;
+    }
+
+    if midpoints.next().is_some() {
+        return false;
+    }
+
+    y == square_times(group, &x, t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::group::RsaGroup;
+    use crate::RSA_2048;
+
+    fn evaluate(
+        group: &RsaGroup,
+        generator: &Int,
+        iterations: u64,
+    ) -> VDFResult {
+        VDFResult {
+            result: square_times(group, generator, iterations),
+            iterations,
+        }
+    }
+
+    #[test]
+    fn proof_verifies_for_odd_and_even_iterations() {
+        let group = RsaGroup::new(Int::from_str_radix(RSA_2048, 10).unwrap());
+        let generator = group.hash_to_element("pietrzak");
+
+        for iterations in &[1, 2, 3, 100, 129] {
+            let output = evaluate(&group, &generator, *iterations);
+            let mu_list = prove(&group, &generator, &output);
+            assert!(verify(&group, &generator, &output, &mu_list));
+        }
+    }
+
+    #[test]
+    fn stored_powers_give_the_same_proof() {
+        let group = RsaGroup::new(Int::from_str_radix(RSA_2048, 10).unwrap());
+        let generator = group.hash_to_element("pietrzak");
+
+        for (iterations, spacing) in &[(2000, 8), (2047, 3), (4099, 1)] {
+            let mut powers = vec![generator.clone()];
+            while (powers.len() as u64) * spacing < *iterations {
+                let last = powers.last().unwrap();
+                powers.push(square_times(&group, last, *spacing));
+            }
+            let output = evaluate(&group, &generator, *iterations);
+            let mu_list = prove_from_powers(
+                &group, &generator, &output, &powers, *spacing,
+            );
+            assert_eq!(mu_list, prove(&group, &generator, &output));
+            assert!(verify(&group, &generator, &output, &mu_list));
+        }
+    }
+
+    #[test]
+    fn proof_for_wrong_output_is_rejected() {
+        let group = RsaGroup::new(Int::from_str_radix(RSA_2048, 10).unwrap());
+        let generator = group.hash_to_element("pietrzak");
+
+        let output = evaluate(&group, &generator, 64);
+        let mu_list = prove(&group, &generator, &output);
+
+        let mut wrong = output.clone();
+        wrong.result = group.multiply(&wrong.result, &Int::from(2));
+        assert!(!verify(&group, &generator, &wrong, &mu_list));
+        assert!(!verify(&group, &generator, &output, &mu_list[1..]));
+    }
+}
@@ -1,9 +1,14 @@
-use crate::vdf::evaluation;
+use crate::error::{self, PoLError};
+use crate::vdf::bigint::Int;
+use crate::vdf::checkpoint::{CheckpointStore, ParallelProofState};
+use crate::vdf::evaluation::{self, Cancellation};
+use crate::vdf::group::{RsaGroup, VdfGroup};
+use crate::vdf::pietrzak;
+use crate::vdf::util;
+use bytecheck::CheckBytes;
 use crossbeam::channel::unbounded;
 use crossbeam::channel::{Receiver, Sender};
 use lazy_static::lazy_static;
-use ramp::Int;
-use rayon::prelude::*;
 use rkyv::{Archive, Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::thread;
@@ -13,49 +18,120 @@ lazy_static! {
     static ref TWO: Int = Int::from_str_radix("2", 10).unwrap();
 }
 
+/// The largest window calculate_windowed accepts, as it needs 2^window
+/// buckets of group elements, some megabytes for 2048 bit elements
+pub const MAX_WINDOW: u64 = 16;
+
+/// Most powers of the generator a VDF stores for a windowed or a Pietrzak
+/// proof, a quarter of a gigabyte for 2048 bit elements. Longer evaluations
+/// stop storing them and calculate their proofs without.
+pub const MAX_STORED_POWERS: usize = 1 << 20;
+
+/// Advances the Wesolowski proof by one iteration. The exponent 2^T / cap is
+/// long divided bit by bit, r being the remainder so far.
+fn proof_step<G: VdfGroup>(
+    group: &G,
+    generator: &G::Element,
+    cap: &Int,
+    state: &mut ParallelProofState<G::Element>,
+) {
+    // b is either 0 or 1
+    let b = &*TWO * &state.r / cap;
+    state.pi = group.square(&state.pi);
+    if b == Int::one() {
+        state.pi = group.multiply(&state.pi, generator);
+    }
+    state.r = &state.r * &*TWO % cap;
+    state.iterations += 1;
+}
+
+/// A deserializable VDFProof, the group and its elements in their canonical
+/// string encodings
 #[derive(
     Archive, Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq,
 )]
+#[archive(derive(CheckBytes))]
 pub struct DeserializableVDFProof {
-    pub modulus: String,
+    pub group: String,
     pub generator: String,
     pub output: evaluation::DeserializableVDFResult,
     pub cap: String,
     pub pi: String,
+    pub mu: Vec<String>,
     pub proof_type: ProofType,
+    pub challenge: ChallengeType,
 }
 
 impl DeserializableVDFProof {
-    pub fn serialize(&self) -> VDFProof {
-        VDFProof {
-            modulus: Int::from_str_radix(&self.modulus, 10).unwrap(),
-            generator: Int::from_str_radix(&self.generator, 10).unwrap(),
-            output: self.output.serialize(),
-            cap: Int::from_str_radix(&self.cap, 10).unwrap(),
-            pi: Int::from_str_radix(&self.pi, 10).unwrap(),
-            proof_type: self.proof_type.clone(),
+    pub fn serialize<G: VdfGroup>(&self) -> Result<VDFProof<G>, PoLError> {
+        VDFProof::try_from(self)
+    }
+
+    /// Verifies the proof, a malformed proof being incorrect
+    pub fn verify<G: VdfGroup>(&self) -> bool {
+        match self.serialize::<G>() {
+            Ok(proof) => proof.verify(),
+            Err(_) => false,
         }
     }
-    pub fn verify(&self) -> bool {
-        self.serialize().verify()
+
+    /// Verifies the proof as a non-interactive proof, a malformed proof being
+    /// incorrect
+    pub fn verify_non_interactive<G: VdfGroup>(&self) -> bool {
+        match self.serialize::<G>() {
+            Ok(proof) => proof.verify_non_interactive(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl<G: VdfGroup> TryFrom<&DeserializableVDFProof> for VDFProof<G> {
+    type Error = PoLError;
+
+    fn try_from(proof: &DeserializableVDFProof) -> Result<Self, PoLError> {
+        let group =
+            G::decode_group(&proof.group).ok_or_else(|| PoLError::Parse {
+                input: proof.group.chars().take(64).collect(),
+            })?;
+        let decode =
+            |s: &String| group.decode(s).ok_or(PoLError::InvalidGroupElement);
+        Ok(VDFProof {
+            generator: decode(&proof.generator)?,
+            output: proof.output.serialize(&group)?,
+            cap: error::parse_int(&proof.cap)?,
+            pi: decode(&proof.pi)?,
+            mu: proof.mu.iter().map(decode).collect::<Result<_, _>>()?,
+            proof_type: proof.proof_type.clone(),
+            challenge: proof.challenge.clone(),
+            group,
+        })
     }
 }
 
-/// Proof of an already calculated VDF that gets passed around between peers
+/// Proof of an already calculated VDF that gets passed around between peers.
+/// Wesolowski proofs consist of pi, Pietrzak proofs of the halving midpoints mu
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
-pub struct VDFProof {
-    pub modulus: Int,
-    pub generator: Int,
-    pub output: evaluation::VDFResult,
+pub struct VDFProof<G: VdfGroup = RsaGroup> {
+    pub group: G,
+    pub generator: G::Element,
+    pub output: evaluation::VDFResult<G::Element>,
     pub cap: Int,
-    pub pi: Int,
+    pub pi: G::Element,
+    pub mu: Vec<G::Element>,
     pub proof_type: ProofType,
+    pub challenge: ChallengeType,
 }
 
+/// How the proof is generated. Sequential, Parallel and Windowed all produce
+/// the same Wesolowski proof, Windowed from powers of the generator stored
+/// during evaluation.
 #[derive(Archive, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[archive(derive(CheckBytes))]
 pub enum ProofType {
     Sequential,
     Parallel,
+    Pietrzak,
+    Windowed,
 }
 
 impl Default for ProofType {
@@ -64,62 +140,129 @@ impl Default for ProofType {
     }
 }
 
-impl VDFProof {
+/// How the cap (the Wesolowski challenge prime) of a proof was chosen.
+/// Interactive caps are picked by the other peer, FiatShamir caps are derived
+/// from the proven statement with util::fiat_shamir_cap, so anyone can check
+/// them.
+#[derive(Archive, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[archive(derive(CheckBytes))]
+pub enum ChallengeType {
+    Interactive,
+    FiatShamir,
+}
+
+impl Default for ChallengeType {
+    fn default() -> Self {
+        ChallengeType::Interactive
+    }
+}
+
+impl<G: VdfGroup> VDFProof<G> {
     /// Returns a VDFProof based on a VDFResult
     pub fn new(
-        modulus: &Int,
-        generator: &Int,
-        result: &evaluation::VDFResult,
+        group: &G,
+        generator: &G::Element,
+        result: &evaluation::VDFResult<G::Element>,
         cap: &Int,
         proof_type: &ProofType,
     ) -> Self {
         Self {
-            modulus: modulus.clone(),
+            group: group.clone(),
             generator: generator.clone(),
             output: result.clone(),
             cap: cap.clone(),
-            pi: Int::zero(),
+            pi: G::Element::default(),
+            mu: Vec::new(),
             proof_type: proof_type.clone(),
+            challenge: ChallengeType::Interactive,
         }
     }
 
+    /// Returns a non-interactive VDFProof based on a VDFResult, the cap being
+    /// derived from the group, generator and the result
+    pub fn new_fiat_shamir(
+        group: &G,
+        generator: &G::Element,
+        result: &evaluation::VDFResult<G::Element>,
+        proof_type: &ProofType,
+    ) -> Self {
+        let cap = util::fiat_shamir_cap(
+            group,
+            generator,
+            result.iterations,
+            &result.result,
+        );
+        let mut proof = Self::new(group, generator, result, &cap, proof_type);
+        proof.challenge = ChallengeType::FiatShamir;
+        proof
+    }
+
     pub fn deserialize(&self) -> DeserializableVDFProof {
         DeserializableVDFProof {
-            modulus: self.modulus.to_str_radix(10, false),
-            generator: self.generator.to_str_radix(10, false),
-            output: self.output.deserialize(),
+            group: self.group.encode_group(),
+            generator: self.group.encode(&self.generator),
+            output: self.output.deserialize(&self.group),
             cap: self.cap.to_str_radix(10, false),
-            pi: self.pi.to_str_radix(10, false),
+            pi: self.group.encode(&self.pi),
+            mu: self.mu.iter().map(|mu| self.group.encode(mu)).collect(),
             proof_type: self.proof_type.clone(),
+            challenge: self.challenge.clone(),
         }
     }
 
     /// Parallel proof calculator. This should be nudged in parallel to the
     /// evaluator, in the end generating a proof on a false nudge value. Cap
     /// must be defined before VDF evaluation.
-    pub fn calculate_parallel(&mut self) -> (Sender<bool>, Receiver<VDFProof>) {
+    pub fn calculate_parallel(
+        &mut self,
+    ) -> (Sender<bool>, Receiver<VDFProof<G>>) {
+        let state = ParallelProofState {
+            iterations: 0,
+            r: Int::from(1),
+            pi: self.group.identity(),
+        };
+        self.calculate_parallel_from(state, None, Cancellation::new())
+    }
+
+    /// Parallel proof calculator that continues from a checkpointed state.
+    /// With a checkpoint store, the state is persisted every interval nudges.
+    pub fn calculate_parallel_from(
+        &mut self,
+        state: ParallelProofState<G::Element>,
+        checkpoints: Option<(CheckpointStore, u64)>,
+        cancellation: Cancellation,
+    ) -> (Sender<bool>, Receiver<VDFProof<G>>) {
         let (nudger, nudge_listener): (Sender<bool>, Receiver<bool>) =
             unbounded();
-        let (sender, output): (Sender<VDFProof>, Receiver<VDFProof>) =
+        let (sender, output): (Sender<VDFProof<G>>, Receiver<VDFProof<G>>) =
             unbounded();
         let mut self_clone = self.clone();
         thread::spawn(move || {
-            let mut r: Int = Int::from(1);
-            let mut b: Int;
-            let modulus: &Int = &self_clone.modulus;
-            let generator: &Int = &self_clone.generator;
+            let mut state = state;
+            let group: &G = &self_clone.group;
+            let generator: &G::Element = &self_clone.generator;
             let cap: &Int = &self_clone.cap;
-            let mut pi = Int::from(1);
 
             while let Ok(nudge) = nudge_listener.recv() {
+                // Nudges still queued are skipped once cancelled
+                if cancellation.is_cancelled() {
+                    break;
+                }
                 match nudge {
                     true => {
-                        // calculate next proof
-                        b = &*TWO * &r / cap;
-                        pi = pi.pow_mod(&*TWO, modulus)
-                            * generator.pow_mod(&b, modulus)
-                            % modulus;
-                        r = r * &*TWO % cap;
+                        proof_step(group, generator, cap, &mut state);
+
+                        if let Some((store, interval)) = checkpoints.as_ref() {
+                            if state.iterations % interval == 0
+                                && store
+                                    .save_proof_state(group, &state)
+                                    .is_err()
+                            {
+                                error!(
+                                    "Couldn't checkpoint the parallel proof!"
+                                );
+                            }
+                        }
                         continue;
                     }
                     false => {
@@ -128,8 +271,12 @@ impl VDFProof {
                 }
             }
 
+            if cancellation.is_cancelled() {
+                debug!("Parallel proof cancelled");
+                return;
+            }
             debug!("Nudger received false, sending current proof");
-            self_clone.pi = pi;
+            self_clone.pi = state.pi;
             if sender.send(self_clone).is_err() {
                 error!("Couldn't send parallelly calculated VDF proof to the evaluator!");
             }
@@ -138,81 +285,214 @@ impl VDFProof {
         (nudger, output)
     }
 
-    pub fn calculate(&mut self) -> Option<VDFProof> {
+    /// Calculates a Wesolowski proof after the evaluation. The proof is
+    /// folded bit by bit like in calculate_parallel, so memory use doesn't
+    /// grow with the iteration count.
+    pub fn calculate(&mut self) -> Option<VDFProof<G>> {
+        self.calculate_with_cancellation(&Cancellation::new())
+            .unwrap_or(None)
+    }
+
+    /// Like calculate, giving up with PoLError::Cancelled as soon as
+    /// cancellation is cancelled
+    pub fn calculate_with_cancellation(
+        &mut self,
+        cancellation: &Cancellation,
+    ) -> Result<Option<VDFProof<G>>, PoLError> {
+        if self.proof_type == ProofType::Pietrzak {
+            return Ok(self.calculate_pietrzak_from_powers(&[], 0));
+        }
+
+        if self.output.iterations == 0 {
+            return Ok(None);
+        }
+
         let timer = Instant::now();
-        match usize::try_from(self.output.iterations) {
-            Err(_) => {
-                error!("Using PoL on a platform with no u32 support!");
-                None
+        let mut state = ParallelProofState {
+            iterations: 0,
+            r: Int::from(1),
+            pi: self.group.identity(),
+        };
+        while state.iterations < self.output.iterations {
+            if cancellation.is_cancelled() {
+                debug!("Proof cancelled after {} steps", state.iterations);
+                return Err(PoLError::Cancelled);
             }
-            Ok(iter) => {
-                match iter {
-                    0 => None,
-                    _ => {
-                        let cap = &self.cap;
-                        let mut r: Vec<Int> = Vec::with_capacity(iter);
-                        r.push(Int::from(1));
-
-                        // Calculate r values
-                        (0..iter)
-                            .skip(1)
-                            .for_each(|i| r.push(&r[i - 1] * &*TWO % cap));
-
-                        // Construct a parallel iterator for values of b
-                        let b = r.into_par_iter().map(|r| &*TWO * r / cap);
-                        let pi_y: Vec<Int> = b
-                            .into_par_iter()
-                            .map(|b| self.generator.pow_mod(&b, &self.modulus))
-                            .collect();
-
-                        debug!(
-                            "Calculating all pi_y has taken {:?} milliseconds",
-                            timer.elapsed().as_millis()
-                        );
-
-                        let pi_last = |mut pi: Int| {
-                            for y in pi_y {
-                                pi = pi.pow_mod(&*TWO, &self.modulus) * y
-                                    % &self.modulus;
-                            }
-                            pi
-                        };
-                        let pi = pi_last(Int::from(1));
-
-                        debug!(
-                            "Proof generation took {:?} milliseconds",
-                            timer.elapsed().as_millis()
-                        );
-
-                        if pi != self.pi {
-                            self.pi = pi;
-                            Some(self.clone())
-                        } else {
-                            None
-                        }
-                    }
-                }
+            proof_step(&self.group, &self.generator, &self.cap, &mut state);
+        }
+
+        debug!(
+            "Proof generation took {:?} milliseconds",
+            timer.elapsed().as_millis()
+        );
+
+        if state.pi != self.pi {
+            self.pi = state.pi;
+            Ok(Some(self.clone()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Calculates a Wesolowski proof from the powers generator^(2^(window*i))
+    /// stored during evaluation, with T/window + 2^(window+1) group
+    /// multiplications instead of T squarings. The quotient 2^T / cap is
+    /// split into digits of window bits, and pi is the product of the powers
+    /// raised to their digits. Falls back to calculate if powers don't cover
+    /// the whole evaluation.
+    pub fn calculate_windowed(
+        &mut self,
+        powers: &[G::Element],
+        window: u64,
+    ) -> Option<VDFProof<G>> {
+        let iterations = self.output.iterations;
+        if iterations == 0 || window == 0 || window > MAX_WINDOW {
+            return self.calculate();
+        }
+        let blocks = (iterations + window - 1) / window;
+        if (powers.len() as u64) < blocks {
+            debug!(
+                "Not enough stored powers, calculating the proof sequentially"
+            );
+            return self.calculate();
+        }
+
+        let timer = Instant::now();
+        let cap = &self.cap;
+
+        // Multiply each power into the bucket of its digit
+        let mut buckets: Vec<G::Element> =
+            vec![self.group.identity(); 1 << window];
+        for (i, power) in powers.iter().take(blocks as usize).enumerate() {
+            let position = window * i as u64;
+            let width = window.min(iterations - position);
+            let r = TWO.pow_mod(&Int::from(iterations - position - width), cap);
+            let quotient = (Int::one() << width as usize) * r / cap;
+            let digit = (0..width as u32)
+                .filter(|bit| quotient.bit(*bit))
+                .fold(0usize, |digit, bit| digit | 1 << bit);
+            if digit > 0 {
+                buckets[digit] = self.group.multiply(&buckets[digit], power);
             }
         }
+
+        // pi = prod(bucket_d^d), as a running product of suffix products
+        let mut suffix = self.group.identity();
+        let mut pi = self.group.identity();
+        for bucket in buckets.iter().skip(1).rev() {
+            suffix = self.group.multiply(&suffix, bucket);
+            pi = self.group.multiply(&pi, &suffix);
+        }
+
+        debug!(
+            "Windowed proof generation took {:?} milliseconds",
+            timer.elapsed().as_millis()
+        );
+
+        if pi != self.pi {
+            self.pi = pi;
+            Some(self.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Calculates a Pietrzak halving proof instead of a Wesolowski proof,
+    /// trading a larger proof for less work on long evaluations. The powers
+    /// generator^(2^(spacing*i)) stored during evaluation save most of the
+    /// squarings of the first halvings, see pietrzak::prove_from_powers.
+    pub fn calculate_pietrzak_from_powers(
+        &mut self,
+        powers: &[G::Element],
+        spacing: u64,
+    ) -> Option<VDFProof<G>> {
+        if self.output.iterations == 0 {
+            return None;
+        }
+
+        let timer = Instant::now();
+        self.mu = pietrzak::prove_from_powers(
+            &self.group,
+            &self.generator,
+            &self.output,
+            powers,
+            spacing,
+        );
+        debug!(
+            "Pietrzak proof generation took {:?} milliseconds",
+            timer.elapsed().as_millis()
+        );
+
+        Some(self.clone())
     }
 
     /// A public function that a receiver can use to verify the correctness of
     /// the VDFProof
     pub fn verify(&self) -> bool {
-        // Check first that the proof belongs in the RSA group
-        if self.pi > self.modulus {
-            return false;
+        if self.proof_type == ProofType::Pietrzak {
+            return self.group.is_element(&self.generator)
+                && self.group.is_element(&self.output.result)
+                && pietrzak::verify(
+                    &self.group,
+                    &self.generator,
+                    &self.output,
+                    &self.mu,
+                );
+        }
+
+        match self.wesolowski_exponent() {
+            None => false,
+            Some(r) => {
+                self.output.result
+                    == self.group.multiply(
+                        &self.group.exponentiate(&self.pi, &self.cap),
+                        &self.group.exponentiate(&self.generator, &r),
+                    )
+            }
+        }
+    }
+
+    /// Verifies the proof as a non-interactive proof, whatever challenge it
+    /// claims. A Wesolowski proof has to claim ChallengeType::FiatShamir, so
+    /// that verify recomputes the cap from the statement instead of trusting
+    /// a cap the prover could have chosen. Pietrzak proofs derive their
+    /// challenges from the statement anyway.
+    pub fn verify_non_interactive(&self) -> bool {
+        (self.proof_type == ProofType::Pietrzak
+            || self.challenge == ChallengeType::FiatShamir)
+            && self.verify()
+    }
+
+    /// Checks everything in a Wesolowski proof except for the final equation
+    /// output = pi^cap * generator^r, returning r = 2^T mod cap
+    pub(crate) fn wesolowski_exponent(&self) -> Option<Int> {
+        // Check first that the elements belong in the group
+        if !self.group.is_element(&self.generator)
+            || !self.group.is_element(&self.output.result)
+            || !self.group.is_element(&self.pi)
+            || self.cap <= Int::one()
+        {
+            return None;
+        }
+        // A non-interactive proof is only valid with the cap derived from its
+        // own statement
+        if self.challenge == ChallengeType::FiatShamir
+            && self.cap
+                != util::fiat_shamir_cap(
+                    &self.group,
+                    &self.generator,
+                    self.output.iterations,
+                    &self.output.result,
+                )
+        {
+            return None;
         }
-        let r = TWO.pow_mod(&Int::from(self.output.iterations), &self.cap);
-        self.output.result
-            == (self.pi.pow_mod(&self.cap, &self.modulus)
-                * self.generator.pow_mod(&r, &self.modulus))
-                % &self.modulus
+        Some(TWO.pow_mod(&Int::from(self.output.iterations), &self.cap))
     }
 
     /// Helper function for calculating the difference in iterations between two
     /// VDFProofs
-    pub fn abs_difference(&self, other: &VDFProof) -> u32 {
+    pub fn abs_difference(&self, other: &VDFProof<G>) -> u64 {
         if self.output > other.output {
             self.output.iterations - other.output.iterations
         } else {
@@ -1,7 +1,8 @@
 extern crate blake3;
+use crate::vdf::bigint::Int;
+use crate::vdf::bigint::Verification;
+use crate::vdf::group::VdfGroup;
 use blake3::Hash;
-use ramp::Int;
-use ramp_primes::Verification;
 use std::str;
 
 /// Hashes the input with blake3 and converts to a (Big)Int
@@ -42,13 +43,33 @@ pub fn hash_to_prime(s: &str, lower_bound: &Int) -> Int {
     result
 }
 
+/// Derives the Wesolowski challenge prime non-interactively (Fiat-Shamir) from
+/// a canonical encoding of the VDF statement. Anyone holding the proof can
+/// recompute it, which makes the proof publicly verifiable.
+pub fn fiat_shamir_cap<G: VdfGroup>(
+    group: &G,
+    generator: &G::Element,
+    iterations: u64,
+    output: &G::Element,
+) -> Int {
+    let statement = format!(
+        "{}|{}|{}|{}",
+        group.encode_group(),
+        group.encode(generator),
+        iterations,
+        group.encode(output)
+    );
+    hash_to_prime(&statement, &(Int::one() << 127))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vdf::bigint::Int;
+    use crate::vdf::bigint::Verification;
+    use crate::vdf::group::RsaGroup;
     use crate::RSA_2048;
     use proptest::prelude::*;
-    use ramp::Int;
-    use ramp_primes::Verification;
 
     proptest! {
         #[test]
@@ -80,4 +101,17 @@ mod tests {
         let prime3 = hash_to_prime(input2, &modulus);
         assert!(prime3 != prime1);
     }
+
+    #[test]
+    fn fiat_shamir_cap_is_bound_to_the_statement() {
+        let group = RsaGroup::new(Int::from_str_radix(RSA_2048, 10).unwrap());
+        let generator = Int::from(3);
+        let output = Int::from(81);
+
+        let cap = fiat_shamir_cap(&group, &generator, 2, &output);
+        assert!(Verification::verify_prime(cap.clone()));
+        assert_eq!(cap, fiat_shamir_cap(&group, &generator, 2, &output));
+        assert!(cap != fiat_shamir_cap(&group, &generator, 3, &output));
+        assert!(cap != fiat_shamir_cap(&group, &output, 2, &generator));
+    }
 }